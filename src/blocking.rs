@@ -0,0 +1,494 @@
+//! A synchronous interface to any `HttpClient`.
+
+use super::{Body, Error, HttpClient, Request, Response};
+
+use futures::channel::mpsc;
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::io::AsyncReadExt;
+use futures::stream::{FuturesUnordered, StreamExt};
+use http_types::headers::{HeaderName, HeaderValues, Headers};
+use http_types::{Mime, StatusCode, Version};
+
+use std::any::Any;
+use std::future::Future;
+use std::io::{self, BufRead, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc as sync_mpsc, Arc};
+use std::task::Poll;
+use std::{fmt, thread};
+
+/// The size of the chunks response bodies are read in.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// A client that sends requests synchronously.
+///
+/// Requests are sent, and response bodies read, on a thread of the client's own, while the
+/// calling thread waits. That makes it safe to use from synchronous code, but also from within an
+/// executor, where blocking on the future in place could deadlock or panic. The thread is shared
+/// between clones of the client, and exits once the client and all its responses are dropped.
+///
+/// That thread doesn't run any particular executor, so clients that only work within one, such as
+/// `HyperClient` within a tokio runtime, fail or panic there. Those are run on their executor
+/// with `with_spawner` instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # fn main() -> Result<(), http_types::Error> {
+/// use http_client::blocking::BlockingClient;
+/// use http_client::h1::H1Client;
+/// use http_types::{Method, Request};
+/// use std::io::Read;
+///
+/// let client = BlockingClient::new(H1Client::new());
+/// let mut res = client.send(Request::new(Method::Get, "http://example.com"))?;
+/// let mut body = String::new();
+/// res.read_to_string(&mut body)?;
+/// println!("{}", body);
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct BlockingClient<C> {
+    inner: Arc<C>,
+    runtime: Runtime,
+}
+
+impl<C> Clone for BlockingClient<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+impl<C: HttpClient> BlockingClient<C> {
+    /// Send requests synchronously with `inner`.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            runtime: Runtime::new(),
+        }
+    }
+
+    /// Send requests synchronously with `inner`, running them with `spawn` rather than on a
+    /// thread of the client's own.
+    ///
+    /// `spawn` is given each request, and each read of a response body, as a future to run to
+    /// completion on an executor, such as a tokio runtime. The calling thread still blocks until
+    /// the future is done, so it mustn't be one the executor needs to make progress, such as the
+    /// thread of a single-threaded runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "hyper_client")]
+    /// # fn main() -> Result<(), http_types::Error> {
+    /// use http_client::blocking::BlockingClient;
+    /// use http_client::hyper::HyperClient;
+    ///
+    /// let runtime = tokio::runtime::Runtime::new()?;
+    /// let handle = runtime.handle().clone();
+    /// let client = BlockingClient::with_spawner(HyperClient::new(), move |job| {
+    ///     handle.spawn(job);
+    /// });
+    /// # Ok(()) }
+    /// # #[cfg(not(feature = "hyper_client"))]
+    /// # fn main() {}
+    /// ```
+    pub fn with_spawner<F>(inner: C, spawn: F) -> Self
+    where
+        F: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(inner),
+            runtime: Runtime {
+                spawn: Arc::new(spawn),
+            },
+        }
+    }
+
+    /// The client requests are sent with.
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Send `req`, and wait for the response to start.
+    ///
+    /// The body of the response is read as the response is.
+    pub fn send(&self, req: Request) -> Result<BlockingResponse, Error> {
+        let client = self.inner.clone();
+        let mut res = self
+            .runtime
+            .block_on(async move { client.send(req).await })??;
+        let body = res.take_body();
+        Ok(BlockingResponse {
+            head: res,
+            len: body.len(),
+            body: Some(body),
+            buf: Vec::new(),
+            pos: 0,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+/// A response to a request sent with a `BlockingClient`.
+///
+/// The body is read with `Read` or `BufRead`.
+pub struct BlockingResponse {
+    /// The response, without its body.
+    head: Response,
+    /// The length of the whole body, if known.
+    len: Option<usize>,
+    /// The rest of the body, unless reading it failed.
+    body: Option<Body>,
+    /// What's been read of the body but not consumed yet.
+    buf: Vec<u8>,
+    pos: usize,
+    runtime: Runtime,
+}
+
+impl fmt::Debug for BlockingResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingResponse")
+            .field("status", &self.head.status())
+            .field("headers", self.headers())
+            .finish()
+    }
+}
+
+impl BlockingResponse {
+    /// The status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.head.status()
+    }
+
+    /// The HTTP version of the response, if known.
+    pub fn version(&self) -> Option<Version> {
+        self.head.version()
+    }
+
+    /// The values of the header `name`, if any.
+    pub fn header(&self, name: impl Into<HeaderName>) -> Option<&HeaderValues> {
+        self.head.header(name)
+    }
+
+    /// All headers of the response.
+    pub fn headers(&self) -> &Headers {
+        self.head.as_ref()
+    }
+
+    /// The content type of the response, if it has one.
+    pub fn content_type(&self) -> Option<Mime> {
+        self.head.content_type()
+    }
+
+    /// The length of the body, if known.
+    pub fn len(&self) -> Option<usize> {
+        self.len
+    }
+
+    /// Whether the body is known to be empty.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len.map(|len| len == 0)
+    }
+}
+
+impl Read for BlockingResponse {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let buf = self.fill_buf()?;
+        let len = buf.len().min(out.len());
+        out[..len].copy_from_slice(&buf[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for BlockingResponse {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() {
+            let mut body = match self.body.take() {
+                Some(body) => body,
+                None => return Ok(&[]),
+            };
+            let mut buf = std::mem::take(&mut self.buf);
+            let (body, buf) = self.runtime.block_on(async move {
+                buf.resize(CHUNK_SIZE, 0);
+                let res = body.read(&mut buf).await.map(|len| buf.truncate(len));
+                (body, res.map(|()| buf))
+            })?;
+            // The body is given up on after an error, like the async body would be.
+            self.pos = 0;
+            self.buf = buf?;
+            if !self.buf.is_empty() {
+                self.body = Some(body);
+            }
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+type Job = BoxFuture<'static, ()>;
+
+type Outcome<T> = Result<T, Box<dyn Any + Send>>;
+
+/// Where a `BlockingClient` runs its futures: a thread of its own, or a caller's executor.
+#[derive(Clone)]
+struct Runtime {
+    spawn: Arc<dyn Fn(Job) + Send + Sync>,
+}
+
+impl fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Runtime").finish()
+    }
+}
+
+impl Runtime {
+    fn new() -> Self {
+        let (jobs, mut queue) = mpsc::unbounded::<Job>();
+        thread::Builder::new()
+            .name("http-client-blocking".to_owned())
+            .spawn(move || {
+                let mut running = FuturesUnordered::new();
+                let mut closed = false;
+                futures::executor::block_on(future::poll_fn(|cx| {
+                    while !closed {
+                        match queue.poll_next_unpin(cx) {
+                            Poll::Ready(Some(job)) => running.push(job),
+                            Poll::Ready(None) => closed = true,
+                            Poll::Pending => break,
+                        }
+                    }
+                    while let Poll::Ready(Some(())) = running.poll_next_unpin(cx) {}
+                    match closed && running.is_empty() {
+                        true => Poll::Ready(()),
+                        false => Poll::Pending,
+                    }
+                }));
+            })
+            .expect("failed to spawn the thread of a blocking client");
+        // Should the thread have stopped, the job is dropped, which `block_on` reports.
+        let spawn = move |job| {
+            let _ = jobs.unbounded_send(job);
+        };
+        Self {
+            spawn: Arc::new(spawn),
+        }
+    }
+
+    /// Run `fut` on the runtime, and wait for its output.
+    ///
+    /// Panics in `fut` are resumed on the calling thread, leaving the runtime running. If the
+    /// runtime drops `fut` before it's done, such as when a caller's executor shuts down, an error
+    /// is returned instead.
+    fn block_on<T, F>(&self, fut: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = T> + Send + 'static,
+    {
+        let (tx, rx) = sync_mpsc::sync_channel::<Outcome<T>>(1);
+        let job = AssertUnwindSafe(fut).catch_unwind().map(move |outcome| {
+            let _ = tx.send(outcome);
+        });
+        (self.spawn)(Box::pin(job));
+        match rx.recv() {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(panic)) => panic::resume_unwind(panic),
+            Err(_) => Err(io::Error::other(
+                "the runtime of a blocking client dropped a future",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+    use futures::stream::{self, TryStreamExt};
+    use http_types::Method;
+
+    fn client() -> BlockingClient<MockClient> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/")
+            .respond(StatusCode::Ok, "hello");
+        mock.expect(Method::Get, "/large").respond_with(|_| {
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body((0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>());
+            res
+        });
+        BlockingClient::new(mock)
+    }
+
+    fn get(path: &str) -> Request {
+        Request::new(Method::Get, format!("http://api.test{}", path).as_str())
+    }
+
+    #[test]
+    fn sends_requests_synchronously() -> http_types::Result<()> {
+        let client = client();
+        let mut res = client.send(get("/"))?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(res.len(), Some(5));
+        let mut body = String::new();
+        res.read_to_string(&mut body)?;
+        assert_eq!(body, "hello");
+
+        let mut res = client.send(get("/large"))?;
+        let mut body = Vec::new();
+        let mut chunk = [0; 1000];
+        loop {
+            match res.read(&mut chunk)? {
+                0 => break,
+                len => body.extend_from_slice(&chunk[..len]),
+            }
+        }
+        assert_eq!(body.len(), 100_000);
+        assert!(body.iter().enumerate().all(|(i, b)| *b == (i % 251) as u8));
+
+        let err = client.send(get("/missing")).unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+        assert_eq!(client.inner().received().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn ends_the_body_after_an_error() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/broken").respond_with(|_| {
+            let chunks = vec![
+                Ok(b"hello".to_vec()),
+                Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset")),
+            ];
+            let mut res = Response::new(StatusCode::Ok);
+            res.set_body(Body::from_reader(
+                stream::iter(chunks).into_async_read(),
+                None,
+            ));
+            res
+        });
+        let client = BlockingClient::new(mock);
+
+        let mut res = client.send(get("/broken"))?;
+        let mut hello = [0; 5];
+        res.read_exact(&mut hello)?;
+        assert_eq!(&hello, b"hello");
+        let err = res.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(res.read(&mut [0; 16])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn works_within_executors() -> http_types::Result<()> {
+        let client = client();
+        let read = |client: &BlockingClient<MockClient>| -> http_types::Result<String> {
+            let mut body = String::new();
+            client.send(get("/"))?.read_to_string(&mut body)?;
+            Ok(body)
+        };
+        assert_eq!(
+            futures::executor::block_on(async { read(&client) })?,
+            "hello"
+        );
+        let clone = client.clone();
+        assert_eq!(
+            async_std::task::block_on(async move { read(&clone) })?,
+            "hello"
+        );
+        Ok(())
+    }
+
+    /// A client that panics for `/panic`, and says hello otherwise.
+    #[derive(Debug)]
+    struct Panicky;
+
+    impl HttpClient for Panicky {
+        fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+            Box::pin(async move {
+                if req.url().path() == "/panic" {
+                    panic!("panicked sending {}", req.url());
+                }
+                let mut res = Response::new(StatusCode::Ok);
+                res.set_body("hello");
+                Ok(res)
+            })
+        }
+    }
+
+    /// A client that only works within a tokio runtime, like `HyperClient`.
+    #[derive(Debug)]
+    struct TokioOnly;
+
+    impl HttpClient for TokioOnly {
+        fn send(&self, _req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+            Box::pin(async move {
+                if let Err(e) = tokio::runtime::Handle::try_current() {
+                    return Err(Error::from_str(StatusCode::InternalServerError, e));
+                }
+                let mut res = Response::new(StatusCode::Ok);
+                res.set_body("hello");
+                Ok(res)
+            })
+        }
+    }
+
+    #[test]
+    fn runs_futures_with_a_spawner() -> http_types::Result<()> {
+        assert!(BlockingClient::new(TokioOnly).send(get("/")).is_err());
+
+        let mut rt = tokio::runtime::Builder::new().basic_scheduler().build()?;
+        let handle = rt.handle().clone();
+        let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+        let driver = thread::spawn(move || rt.block_on(stopped));
+        let client = BlockingClient::with_spawner(TokioOnly, move |job| {
+            handle.spawn(job);
+        });
+        let mut body = String::new();
+        client.send(get("/"))?.read_to_string(&mut body)?;
+        assert_eq!(body, "hello");
+        drop(stop);
+        let _ = driver.join();
+        Ok(())
+    }
+
+    #[test]
+    fn reports_futures_dropped_by_the_spawner() -> http_types::Result<()> {
+        let client = BlockingClient::with_spawner(Panicky, drop);
+        let err = client.send(get("/")).unwrap_err();
+        assert!(err.to_string().contains("dropped"), "{}", err);
+
+        // Run the request, but drop the read of the body.
+        let spawned = std::sync::atomic::AtomicUsize::new(0);
+        let client = BlockingClient::with_spawner(Panicky, move |job| {
+            if spawned.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                futures::executor::block_on(job);
+            }
+        });
+        let mut res = client.send(get("/"))?;
+        let err = res.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(res.read(&mut [0; 16])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_panics_on_the_calling_thread() -> http_types::Result<()> {
+        let client = BlockingClient::new(Panicky);
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| client.send(get("/panic"))));
+        assert!(panicked.is_err());
+        let mut body = String::new();
+        client.send(get("/"))?.read_to_string(&mut body)?;
+        assert_eq!(body, "hello");
+        Ok(())
+    }
+}
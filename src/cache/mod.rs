@@ -0,0 +1,712 @@
+//! Caching responses, for any `HttpClient`.
+//!
+//! `Cache` is a private cache following [RFC 9111]: it stores the responses to `GET` requests
+//! that may be stored, serves them while they're fresh, and revalidates them with the server once
+//! they're stale. Where responses are kept is up to a `CacheStorage`; `MemoryStorage` and
+//! `DiskStorage` are provided.
+//!
+//! [RFC 9111]: https://www.rfc-editor.org/rfc/rfc9111
+
+use super::{Body, Error, HttpClient, Request, Response};
+
+use futures::future::BoxFuture;
+use futures::io::{AsyncReadExt, Cursor};
+use http_types::headers::{
+    AGE, CACHE_CONTROL, CONTENT_TYPE, DATE, ETAG, EXPIRES, IF_MATCH, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE, LAST_MODIFIED, VARY,
+};
+use http_types::other::Date;
+use http_types::url::Url;
+use http_types::{Method, StatusCode};
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+mod storage;
+
+pub use storage::{CacheStorage, CachedResponse, DiskStorage, MemoryStorage};
+
+/// The longest freshness lifetime guessed from `Last-Modified`.
+const MAX_HEURISTIC_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A client that caches responses.
+///
+/// Responses to `GET` requests are stored unless either side forbids it with `no-store`, and
+/// served from the cache while fresh according to their `Cache-Control`, `Expires`, `Date` and
+/// `Age` headers. Responses without an explicit lifetime are kept fresh for a tenth of the time
+/// since their `Last-Modified` date, up to a day. Served responses get an `Age` header.
+///
+/// Stale responses with an `ETag` or `Last-Modified` header are revalidated with `If-None-Match`
+/// or `If-Modified-Since`; when the server answers `304 Not Modified`, the stored response is
+/// refreshed and returned in its place. Responses are stored along with the request headers named
+/// by their `Vary` header, and only served to requests with the same values.
+///
+/// The request directives `max-age`, `min-fresh`, `max-stale`, `no-cache`, `no-store` and
+/// `only-if-cached` are honored. Requests that are conditional already, or ask for a range, go
+/// straight to the server. Successful requests with unsafe methods, such as `POST`, remove the
+/// response stored for their URL.
+///
+/// Bodies are buffered to be stored, up to 8 MiB by default; larger responses are passed on as
+/// they're read, and not stored.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::cache::{Cache, DiskStorage};
+/// use http_client::h1::H1Client;
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+///
+/// let client = Cache::with_storage(H1Client::new(), DiskStorage::new("target/http-cache"));
+/// for _ in 0..2 {
+///     let mut res = client.send(Request::new(Method::Get, "http://example.com")).await?;
+///     println!("{}", res.body_string().await?);
+/// }
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct Cache<C, S = MemoryStorage> {
+    inner: Arc<C>,
+    storage: Arc<S>,
+    max_body: usize,
+}
+
+impl<C, S> Clone for Cache<C, S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            storage: self.storage.clone(),
+            max_body: self.max_body,
+        }
+    }
+}
+
+impl<C: HttpClient> Cache<C> {
+    /// Cache the responses to requests sent with `inner`, in memory.
+    pub fn new(inner: C) -> Self {
+        Self::with_storage(inner, MemoryStorage::default())
+    }
+}
+
+impl<C: HttpClient, S: CacheStorage> Cache<C, S> {
+    /// Cache the responses to requests sent with `inner`, in `storage`.
+    pub fn with_storage(inner: C, storage: S) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            storage: Arc::new(storage),
+            max_body: 8 * 1024 * 1024,
+        }
+    }
+
+    /// Only store responses with bodies of up to `max_body` bytes.
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    /// The storage responses are kept in.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    async fn cached_send(&self, mut req: Request) -> Result<Response, Error> {
+        let key = cache_key(req.url());
+        if req.method() != Method::Get {
+            let safe = matches!(req.method(), Method::Head | Method::Options | Method::Trace);
+            let res = self.inner.send(req).await?;
+            let status = res.status();
+            if !safe && !status.is_client_error() && !status.is_server_error() {
+                self.storage.remove(&key).await;
+            }
+            return Ok(res);
+        }
+
+        let req_cc = Directives::of_request(&req);
+        let conditional = [
+            IF_MATCH,
+            IF_NONE_MATCH,
+            IF_MODIFIED_SINCE,
+            IF_UNMODIFIED_SINCE,
+        ]
+        .iter()
+        .any(|name| req.header(name).is_some());
+        if conditional || req.header(IF_RANGE).is_some() || req.header("range").is_some() {
+            return self.inner.send(req).await;
+        }
+
+        let stored = self
+            .storage
+            .get(&key)
+            .await
+            .filter(|stored| stored.varies_as(&req));
+        if let Some(stored) = &stored {
+            let now = SystemTime::now();
+            if stored.usable(&req_cc, now) {
+                log::trace!("serving {} from the cache", key);
+                return stored.to_response(now);
+            }
+        }
+        if req_cc.get("only-if-cached").is_some() {
+            return Ok(Response::new(StatusCode::GatewayTimeout));
+        }
+
+        if let Some(stored) = &stored {
+            if let Some(etag) = stored.header(ETAG.as_str()) {
+                req.insert_header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = stored.header(LAST_MODIFIED.as_str()) {
+                req.insert_header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let headers = req.clone();
+        let request_time = SystemTime::now();
+        let mut res = self.inner.send(req).await?;
+        let response_time = SystemTime::now();
+
+        if let (Some(mut stored), StatusCode::NotModified) = (stored, res.status()) {
+            log::trace!("revalidated {}", key);
+            stored.refresh(&res, request_time, response_time);
+            let refreshed = stored.to_response(SystemTime::now());
+            if !req_cc.has("no-store") {
+                self.storage.put(&key, stored).await;
+            }
+            return refreshed;
+        }
+
+        let mut entry = CachedResponse {
+            status: res.status().into(),
+            headers: header_pairs(&res),
+            body: Vec::new(),
+            request_time,
+            response_time,
+            vary: Vec::new(),
+        };
+        if req_cc.has("no-store") || !entry.storable() {
+            return Ok(res);
+        }
+        entry.vary = entry
+            .vary_names()
+            .into_iter()
+            .map(|name| {
+                let values = header_values(&headers, &name);
+                (name, values)
+            })
+            .collect();
+        if let Some(body) = self.buffer(&mut res).await? {
+            entry.body = body;
+            self.storage.put(&key, entry).await;
+        }
+        Ok(res)
+    }
+
+    /// Read the body of `res` if it's small enough to store, and put it back.
+    async fn buffer(&self, res: &mut Response) -> Result<Option<Vec<u8>>, Error> {
+        let mut body = res.take_body();
+        if body.len().is_some_and(|len| len > self.max_body) {
+            set_body(res, body);
+            return Ok(None);
+        }
+        let mut buf = Vec::new();
+        let limit = self.max_body as u64 + 1;
+        AsyncReadExt::take(&mut body, limit)
+            .read_to_end(&mut buf)
+            .await?;
+        if buf.len() <= self.max_body {
+            set_body(res, Body::from_bytes(buf.clone()));
+            return Ok(Some(buf));
+        }
+        // Too large to store: send on what's been read, followed by the rest.
+        let rest = Body::from_reader(Cursor::new(buf).chain(body), None);
+        set_body(res, rest);
+        Ok(None)
+    }
+}
+
+impl<C: HttpClient, S: CacheStorage> HttpClient for Cache<C, S> {
+    fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let client = self.clone();
+        Box::pin(async move { client.cached_send(req).await })
+    }
+}
+
+impl CachedResponse {
+    /// Whether the response may be stored.
+    fn storable(&self) -> bool {
+        let cc = self.directives();
+        let status = self.status;
+        if cc.has("no-store") || status < 200 || status == 206 || status == 304 {
+            return false;
+        }
+        if self.vary_names().iter().any(|name| name == "*") {
+            return false;
+        }
+        let explicit = cc.get("max-age").is_some() || self.header(EXPIRES.as_str()).is_some();
+        let validated =
+            self.header(ETAG.as_str()).is_some() || self.header(LAST_MODIFIED.as_str()).is_some();
+        explicit || (cacheable_by_default(status) && validated)
+    }
+
+    /// Whether the response can be served to a request with the directives `req_cc`, without
+    /// asking the server.
+    fn usable(&self, req_cc: &Directives, now: SystemTime) -> bool {
+        let cc = self.directives();
+        if req_cc.has("no-cache") || cc.has("no-cache") {
+            return false;
+        }
+        let lifetime = self.freshness_lifetime(&cc);
+        let age = self.current_age(now);
+        if req_cc
+            .seconds("max-age")
+            .is_some_and(|max_age| age > max_age)
+        {
+            return false;
+        }
+        if let Some(min_fresh) = req_cc.seconds("min-fresh") {
+            return lifetime > age.saturating_add(min_fresh);
+        }
+        if lifetime > age {
+            return true;
+        }
+        if cc.has("must-revalidate") {
+            return false;
+        }
+        match req_cc.get("max-stale") {
+            Some(Some(max_stale)) => age - lifetime <= delta_seconds(max_stale),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// How long the response stays fresh, as in RFC 9111 section 4.2.1.
+    fn freshness_lifetime(&self, cc: &Directives) -> Duration {
+        if let Some(max_age) = cc.seconds("max-age") {
+            return max_age;
+        }
+        let date = self.date();
+        if let Some(expires) = self.header(EXPIRES.as_str()) {
+            // Invalid dates, such as "0", mean the response has already expired.
+            let expires = parse_date(expires);
+            let lifetime = expires.and_then(|expires| expires.duration_since(date).ok());
+            return lifetime.unwrap_or_default();
+        }
+        let last_modified = self.header(LAST_MODIFIED.as_str()).and_then(parse_date);
+        match last_modified {
+            Some(last_modified) if cacheable_by_default(self.status) => {
+                let since = date.duration_since(last_modified).unwrap_or_default();
+                (since / 10).min(MAX_HEURISTIC_LIFETIME)
+            }
+            _ => Duration::default(),
+        }
+    }
+
+    /// How old the response is, as in RFC 9111 section 4.2.3.
+    fn current_age(&self, now: SystemTime) -> Duration {
+        let since = |later: SystemTime, earlier: SystemTime| {
+            later.duration_since(earlier).unwrap_or_default()
+        };
+        let age = self
+            .header(AGE.as_str())
+            .map(|age| delta_seconds(age.trim()))
+            .unwrap_or_default();
+        let apparent_age = since(self.response_time, self.date());
+        let response_delay = since(self.response_time, self.request_time);
+        let corrected_initial_age = apparent_age.max(age.saturating_add(response_delay));
+        corrected_initial_age.saturating_add(since(now, self.response_time))
+    }
+
+    /// The `Date` of the response, or when it was received if it has none.
+    fn date(&self) -> SystemTime {
+        self.header(DATE.as_str())
+            .and_then(parse_date)
+            .unwrap_or(self.response_time)
+    }
+
+    fn directives(&self) -> Directives {
+        Directives::parse(self.header_list(CACHE_CONTROL.as_str()).as_deref())
+    }
+
+    /// The lowercased names of the request headers listed by `Vary`.
+    fn vary_names(&self) -> Vec<String> {
+        let vary = self.header_list(VARY.as_str()).unwrap_or_default();
+        vary.split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    /// Whether `req` has the same values as the stored request for the headers named by `Vary`.
+    fn varies_as(&self, req: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, values)| header_values(req, name) == *values)
+    }
+
+    /// Update the response with the headers of a `304 Not Modified` response to a revalidation.
+    fn refresh(&mut self, res: &Response, request_time: SystemTime, response_time: SystemTime) {
+        for (name, values) in res.iter() {
+            let name = name.as_str();
+            if name.eq_ignore_ascii_case("content-length") {
+                continue;
+            }
+            self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+            for value in values.iter() {
+                self.headers
+                    .push((name.to_owned(), value.as_str().to_owned()));
+            }
+        }
+        self.request_time = request_time;
+        self.response_time = response_time;
+    }
+
+    fn to_response(&self, now: SystemTime) -> Result<Response, Error> {
+        let mut res = Response::new(StatusCode::try_from(self.status)?);
+        for (name, value) in &self.headers {
+            res.append_header(name.as_str(), value.as_str());
+        }
+        set_body(&mut res, Body::from_bytes(self.body.clone()));
+        res.insert_header(AGE, self.current_age(now).as_secs().to_string());
+        Ok(res)
+    }
+}
+
+/// The directives of a `Cache-Control` header, with lowercased names.
+#[derive(Debug, Default)]
+struct Directives(Vec<(String, Option<String>)>);
+
+impl Directives {
+    fn parse(value: Option<&str>) -> Self {
+        let directives = value
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|directive| {
+                let mut parts = directive.splitn(2, '=');
+                let name = parts.next()?.trim().to_ascii_lowercase();
+                let argument = parts
+                    .next()
+                    .map(|arg| arg.trim().trim_matches('"').to_owned());
+                Some((name, argument)).filter(|(name, _)| !name.is_empty())
+            });
+        Self(directives.collect())
+    }
+
+    /// The directives of a request, where `Pragma: no-cache` stands in for a missing
+    /// `Cache-Control` header.
+    fn of_request(req: &Request) -> Self {
+        if req.header(CACHE_CONTROL).is_some() {
+            return Self::parse(Some(&header_values(req, "cache-control").join(",")));
+        }
+        let pragma = header_values(req, "pragma");
+        match pragma.iter().any(|value| value.contains("no-cache")) {
+            true => Self::parse(Some("no-cache")),
+            false => Self::default(),
+        }
+    }
+
+    /// The argument of the directive `name`, if it's there.
+    fn get(&self, name: &str) -> Option<Option<&str>> {
+        let (_, argument) = self.0.iter().find(|(n, _)| n == name)?;
+        Some(argument.as_deref())
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The argument of the directive `name`, as a number of seconds.
+    fn seconds(&self, name: &str) -> Option<Duration> {
+        self.get(name)
+            .map(|argument| delta_seconds(argument.unwrap_or_default()))
+    }
+}
+
+/// Parse a number of seconds, as in RFC 9111 section 1.2.2.
+///
+/// Numbers too large are capped, and invalid ones are taken as zero, which errs on the side of
+/// asking the server.
+fn delta_seconds(value: &str) -> Duration {
+    let secs = match value.parse::<u64>() {
+        Ok(secs) => secs.min(1 << 31),
+        Err(_) if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => 1 << 31,
+        Err(_) => 0,
+    };
+    Duration::from_secs(secs)
+}
+
+/// Whether responses with `status` may get a heuristic freshness lifetime.
+fn cacheable_by_default(status: u16) -> bool {
+    matches!(
+        status,
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// The key responses to requests for `url` are stored under.
+fn cache_key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url.to_string()
+}
+
+/// Parse an HTTP date, in any of the formats `http_types` accepts.
+fn parse_date(value: &str) -> Option<SystemTime> {
+    let mut headers = Response::new(StatusCode::Ok);
+    headers.insert_header(DATE, value);
+    Date::from_headers(&headers)
+        .ok()
+        .flatten()
+        .map(SystemTime::from)
+}
+
+fn header_pairs(res: &Response) -> Vec<(String, String)> {
+    res.iter()
+        .flat_map(|(name, values)| {
+            values
+                .iter()
+                .map(move |value| (name.as_str().to_owned(), value.as_str().to_owned()))
+        })
+        .collect()
+}
+
+fn header_values(req: &Request, name: &str) -> Vec<String> {
+    match req.header(name) {
+        Some(values) => values.iter().map(|v| v.as_str().to_owned()).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Set the body of `res`, without giving it a `Content-Type` it didn't have.
+fn set_body(res: &mut Response, body: Body) {
+    let has_content_type = res.header(CONTENT_TYPE).is_some();
+    res.set_body(body);
+    if !has_content_type {
+        res.remove_header(CONTENT_TYPE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+
+    fn get(url: &str) -> Request {
+        Request::new(Method::Get, format!("http://api.test{}", url).as_str())
+    }
+
+    fn with_headers(
+        body: &'static str,
+        headers: &'static [(&'static str, &'static str)],
+    ) -> impl Fn(&crate::mock::ReceivedRequest) -> Response {
+        move |_| {
+            let mut res = Response::new(StatusCode::Ok);
+            for (name, value) in headers {
+                res.append_header(*name, *value);
+            }
+            res.set_body(body);
+            res
+        }
+    }
+
+    #[async_std::test]
+    async fn serves_fresh_responses() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/fresh")
+            .times(2)
+            .respond_with(with_headers("fresh", &[("cache-control", "max-age=60")]));
+        mock.expect(Method::Get, "/private")
+            .times(2)
+            .respond_with(with_headers("private", &[("cache-control", "no-store")]));
+        let client = Cache::new(mock.clone());
+
+        let mut res = client.send(get("/fresh#top")).await?;
+        assert_eq!(res.body_string().await?, "fresh");
+        assert!(res.header(AGE).is_none());
+        let mut res = client.send(get("/fresh")).await?;
+        assert_eq!(res.body_string().await?, "fresh");
+        assert_eq!(res[AGE], "0");
+        assert_eq!(res[CACHE_CONTROL], "max-age=60");
+
+        let mut req = get("/fresh");
+        req.insert_header(CACHE_CONTROL, "no-cache");
+        assert_eq!(client.send(req).await?.body_string().await?, "fresh");
+
+        for _ in 0..2 {
+            let mut res = client.send(get("/private")).await?;
+            assert_eq!(res.body_string().await?, "private");
+        }
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn revalidates_stale_responses() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/stale")
+            .header("if-none-match", "\"v1\"")
+            .respond_with(|_| {
+                let mut res = Response::new(StatusCode::NotModified);
+                res.insert_header("x-revalidated", "yes");
+                res
+            });
+        mock.expect(Method::Get, "/stale")
+            .times(1)
+            .respond_with(with_headers(
+                "stale",
+                &[("cache-control", "max-age=0"), ("etag", "\"v1\"")],
+            ));
+        let client = Cache::new(mock.clone());
+
+        assert_eq!(
+            client.send(get("/stale")).await?.body_string().await?,
+            "stale"
+        );
+        let mut res = client.send(get("/stale")).await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(res["x-revalidated"], "yes");
+        assert_eq!(res.body_string().await?, "stale");
+
+        let mut req = get("/stale");
+        req.insert_header(CACHE_CONTROL, "max-stale");
+        client.send(req).await?;
+        let mut req = get("/stale");
+        req.insert_header(CACHE_CONTROL, "only-if-cached, max-stale=60");
+        assert_eq!(client.send(req).await?.status(), StatusCode::Ok);
+        assert_eq!(mock.received().len(), 2);
+
+        let mut req = get("/other");
+        req.insert_header(CACHE_CONTROL, "only-if-cached");
+        assert_eq!(client.send(req).await?.status(), StatusCode::GatewayTimeout);
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn keeps_one_variant_per_url() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/").times(3).respond_with(
+            |req: &crate::mock::ReceivedRequest| {
+                let mut res = Response::new(StatusCode::Ok);
+                res.insert_header("cache-control", "max-age=60");
+                res.insert_header("vary", "Accept-Language");
+                res.set_body(req.header("accept-language").join(","));
+                res
+            },
+        );
+        let client = Cache::new(mock.clone());
+        let lang = |lang: &str| {
+            let mut req = get("/");
+            req.insert_header("accept-language", lang);
+            req
+        };
+
+        assert_eq!(client.send(lang("en")).await?.body_string().await?, "en");
+        assert_eq!(client.send(lang("en")).await?.body_string().await?, "en");
+        assert_eq!(client.send(lang("fr")).await?.body_string().await?, "fr");
+        assert_eq!(client.send(get("/")).await?.body_string().await?, "");
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn unsafe_methods_invalidate() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/items")
+            .times(2)
+            .respond_with(with_headers("items", &[("cache-control", "max-age=60")]));
+        mock.expect(Method::Post, "/items")
+            .respond(StatusCode::Created, "");
+        let client = Cache::new(mock.clone());
+
+        client.send(get("/items")).await?;
+        client.send(get("/items")).await?;
+        client
+            .send(Request::new(Method::Post, "http://api.test/items"))
+            .await?;
+        client.send(get("/items")).await?;
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn passes_on_large_bodies() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/large")
+            .times(2)
+            .respond_with(|_| {
+                let mut res = Response::new(StatusCode::Ok);
+                res.insert_header("cache-control", "max-age=60");
+                let body = Cursor::new(b"0123456789".to_vec());
+                res.set_body(Body::from_reader(futures::io::BufReader::new(body), None));
+                res
+            });
+        let client = Cache::new(mock.clone()).max_body(4);
+
+        for _ in 0..2 {
+            let mut res = client.send(get("/large")).await?;
+            assert_eq!(res.body_string().await?, "0123456789");
+        }
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn computes_freshness() {
+        let now = SystemTime::now();
+        let date = Date::new(now - Duration::from_secs(100))
+            .value()
+            .to_string();
+        let last_modified = Date::new(now - Duration::from_secs(10000))
+            .value()
+            .to_string();
+        let response = |headers: &[(&str, &str)]| CachedResponse {
+            status: 200,
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            body: Vec::new(),
+            request_time: now - Duration::from_secs(2),
+            response_time: now,
+            vary: Vec::new(),
+        };
+        let lifetime = |res: &CachedResponse| res.freshness_lifetime(&res.directives()).as_secs();
+
+        let res = response(&[("cache-control", "public, Max-Age=\"30\""), ("date", &date)]);
+        assert_eq!(lifetime(&res), 30);
+        assert_eq!(res.current_age(now).as_secs(), 100);
+        assert!(!res.usable(&Directives::default(), now));
+        assert!(res.usable(&Directives::parse(Some("max-stale=71")), now));
+        assert!(!res.usable(&Directives::parse(Some("max-stale=69")), now));
+
+        let res = response(&[("date", &date), ("expires", "0"), ("age", "5")]);
+        assert_eq!(lifetime(&res), 0);
+        assert_eq!(res.current_age(now + Duration::from_secs(1)).as_secs(), 101);
+
+        let res = response(&[("last-modified", &last_modified), ("age", "5")]);
+        assert_eq!(lifetime(&res), 1000);
+        assert_eq!(res.current_age(now).as_secs(), 7);
+        assert!(res.usable(&Directives::parse(Some("min-fresh=900")), now));
+        assert!(!res.usable(&Directives::parse(Some("max-age=6")), now));
+
+        // Ages too large are capped rather than overflowing.
+        let res = response(&[
+            ("cache-control", "max-age=60"),
+            ("age", "18446744073709551615"),
+        ]);
+        assert_eq!(res.current_age(now).as_secs(), (1 << 31) + 2);
+        assert!(!res.usable(&Directives::default(), now));
+        let res = response(&[
+            ("cache-control", "max-age=60"),
+            ("age", "99999999999999999999"),
+        ]);
+        assert_eq!(res.current_age(now).as_secs(), (1 << 31) + 2);
+        let res = response(&[("cache-control", "max-age=60")]);
+        let min_fresh = format!("min-fresh={}", u64::MAX);
+        assert!(!res.usable(&Directives::parse(Some(&min_fresh)), now));
+    }
+}
@@ -0,0 +1,473 @@
+//! Where a `Cache` keeps its responses.
+
+use futures::future::{self, BoxFuture};
+use serde_json::{json, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+/// Storage for the responses of a `Cache`, by key.
+///
+/// Implementations decide how many responses to keep, and which to drop when full. Errors should
+/// be logged and treated as misses rather than surfaced, so that a broken cache only costs
+/// requests. Storages that do blocking I/O should do it off the executor, as `DiskStorage` does.
+pub trait CacheStorage: Debug + Send + Sync + 'static {
+    /// Get the response stored under `key`.
+    fn get(&self, key: &str) -> BoxFuture<'static, Option<CachedResponse>>;
+
+    /// Store `response` under `key`, replacing any response stored there.
+    fn put(&self, key: &str, response: CachedResponse) -> BoxFuture<'static, ()>;
+
+    /// Remove the response stored under `key`.
+    fn remove(&self, key: &str) -> BoxFuture<'static, ()>;
+}
+
+/// A response stored in a cache, with its body and what's needed to tell its age.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+    /// When the request that got the response was sent.
+    pub(crate) request_time: SystemTime,
+    /// When the response was received.
+    pub(crate) response_time: SystemTime,
+    /// The request headers named by `Vary`, and their values.
+    pub(crate) vary: Vec<(String, Vec<String>)>,
+}
+
+impl CachedResponse {
+    /// The status code of the response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// The body of the response.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The last value of the header `name`, if any.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// All values of the header `name`, joined by commas.
+    pub(crate) fn header_list(&self, name: &str) -> Option<String> {
+        let values: Vec<_> = self
+            .headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect();
+        match values.is_empty() {
+            true => None,
+            false => Some(values.join(", ")),
+        }
+    }
+
+    /// Serialize the response, for storages that keep bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let secs = |time: SystemTime| {
+            let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            since.as_secs_f64()
+        };
+        let json = json!({
+            "status": self.status,
+            "headers": self.headers.iter().map(|(n, v)| json!([n, v])).collect::<Vec<_>>(),
+            "body": base64::encode(&self.body),
+            "request_time": secs(self.request_time),
+            "response_time": secs(self.response_time),
+            "vary": self.vary.iter().map(|(n, v)| json!([n, v])).collect::<Vec<_>>(),
+        });
+        json.to_string().into_bytes()
+    }
+
+    /// Deserialize a response serialized with `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let json: Value = serde_json::from_slice(bytes)?;
+        Self::from_json(&json)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid cached response"))
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        let time = |key: &str| {
+            let secs = json[key].as_f64().filter(|secs| *secs >= 0.0)?;
+            Some(UNIX_EPOCH + Duration::from_secs_f64(secs))
+        };
+        let pairs = |key: &str| json[key].as_array().cloned().unwrap_or_default();
+        let headers = pairs("headers")
+            .iter()
+            .map(|h| Some((h[0].as_str()?.to_owned(), h[1].as_str()?.to_owned())))
+            .collect::<Option<_>>()?;
+        let vary = pairs("vary")
+            .iter()
+            .map(|v| {
+                let values = v[1].as_array()?.iter();
+                let values = values.map(|v| v.as_str().map(str::to_owned));
+                Some((v[0].as_str()?.to_owned(), values.collect::<Option<_>>()?))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            status: json["status"].as_u64()? as u16,
+            headers,
+            body: base64::decode(json["body"].as_str()?).ok()?,
+            request_time: time("request_time")?,
+            response_time: time("response_time")?,
+            vary,
+        })
+    }
+}
+
+/// Storage in memory, dropping the least recently used responses once full.
+///
+/// The storage is full once it holds `capacity` responses, or once their bodies and headers take
+/// up more than the maximum size, 16 MiB by default. Responses bigger than that aren't stored.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    capacity: usize,
+    max_size: usize,
+    state: Mutex<Lru>,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    /// The responses, and when they were last used.
+    entries: HashMap<String, (CachedResponse, u64)>,
+    /// The keys of the responses, by when they were last used.
+    order: BTreeMap<u64, String>,
+    /// The size of the responses, as counted by `size_of`.
+    size: usize,
+    clock: u64,
+}
+
+impl Lru {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, key: &str) -> Option<CachedResponse> {
+        let (response, used) = self.entries.remove(key)?;
+        self.order.remove(&used);
+        self.size -= size_of(&response);
+        Some(response)
+    }
+
+    fn pop_oldest(&mut self) -> Option<CachedResponse> {
+        let (_, key) = self.order.pop_first()?;
+        let (response, _) = self.entries.remove(&key)?;
+        self.size -= size_of(&response);
+        Some(response)
+    }
+}
+
+/// The memory a response takes up, counting its body and headers.
+fn size_of(response: &CachedResponse) -> usize {
+    let headers = response.headers.iter();
+    response.body.len() + headers.map(|(n, v)| n.len() + v.len()).sum::<usize>()
+}
+
+impl MemoryStorage {
+    /// Create a storage for up to `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            max_size: 16 * 1024 * 1024,
+            state: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// Keep the responses under `max_size` bytes in total.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+impl Default for MemoryStorage {
+    /// Create a storage for up to 1000 responses.
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+impl MemoryStorage {
+    fn get_now(&self, key: &str) -> Option<CachedResponse> {
+        let mut lru = self.state.lock().unwrap();
+        let clock = lru.tick();
+        let (response, used) = lru.entries.get_mut(key)?;
+        let last_used = std::mem::replace(used, clock);
+        let response = response.clone();
+        if let Some(key) = lru.order.remove(&last_used) {
+            lru.order.insert(clock, key);
+        }
+        Some(response)
+    }
+
+    fn put_now(&self, key: &str, response: CachedResponse) {
+        let mut lru = self.state.lock().unwrap();
+        lru.remove(key);
+        let size = size_of(&response);
+        if size > self.max_size || self.capacity == 0 {
+            return;
+        }
+        while lru.entries.len() >= self.capacity || lru.size + size > self.max_size {
+            if lru.pop_oldest().is_none() {
+                break;
+            }
+        }
+        let clock = lru.tick();
+        lru.size += size;
+        lru.order.insert(clock, key.to_owned());
+        lru.entries.insert(key.to_owned(), (response, clock));
+    }
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, key: &str) -> BoxFuture<'static, Option<CachedResponse>> {
+        Box::pin(future::ready(self.get_now(key)))
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) -> BoxFuture<'static, ()> {
+        self.put_now(key, response);
+        Box::pin(future::ready(()))
+    }
+
+    fn remove(&self, key: &str) -> BoxFuture<'static, ()> {
+        self.state.lock().unwrap().remove(key);
+        Box::pin(future::ready(()))
+    }
+}
+
+/// Storage in files in a directory, one per response.
+///
+/// The directory is created when the first response is stored. Files are read and written on a
+/// thread pool rather than on the executor. Once they take up more than the maximum size, 64 MiB
+/// by default, the files of the least recently used responses are removed.
+#[derive(Debug, Clone)]
+pub struct DiskStorage {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl DiskStorage {
+    /// Store responses in the directory `dir`.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            max_size: 64 * 1024 * 1024,
+        }
+    }
+
+    /// Keep the files in the directory under `max_size` bytes in total.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// The file for `key`, named after a hash that stays stable across builds.
+    fn path(&self, key: &str) -> PathBuf {
+        // 64-bit FNV-1a.
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.dir.join(format!("{:016x}.json", hash))
+    }
+
+    fn write(&self, key: &str, response: &CachedResponse) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = key.as_bytes().to_vec();
+        file.push(b'\n');
+        file.extend(response.to_bytes());
+        // Write to a temporary file first, so that readers never see half a response.
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, file)?;
+        fs::rename(&tmp, &path)?;
+        self.evict()
+    }
+
+    fn read(&self, key: &str) -> io::Result<Option<CachedResponse>> {
+        let path = self.path(key);
+        let file = match fs::read(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // The key is stored along with the response, in case two keys have the same hash.
+        let newline = file.iter().position(|b| *b == b'\n').unwrap_or(file.len());
+        if &file[..newline] != key.as_bytes() {
+            return Ok(None);
+        }
+        let response = CachedResponse::from_bytes(&file[(newline + 1).min(file.len())..])?;
+        // The modification time tells eviction when the response was last used.
+        let touched = fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = touched {
+            log::debug!("failed to mark {} as used: {}", path.display(), e);
+        }
+        Ok(Some(response))
+    }
+
+    /// Remove the files of the least recently used responses, until the rest fit in `max_size`.
+    fn evict(&self) -> io::Result<()> {
+        let mut files = Vec::new();
+        let mut size = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let path = entry.path();
+            if metadata.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                size += metadata.len();
+                files.push((metadata.modified()?, metadata.len(), path));
+            }
+        }
+        files.sort();
+        for (_, len, path) in files {
+            if size <= self.max_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => size -= len,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CacheStorage for DiskStorage {
+    fn get(&self, key: &str) -> BoxFuture<'static, Option<CachedResponse>> {
+        let (storage, key) = (self.clone(), key.to_owned());
+        Box::pin(blocking::unblock(move || {
+            storage.read(&key).unwrap_or_else(|e| {
+                log::warn!("failed to read cached response for {}: {}", key, e);
+                None
+            })
+        }))
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) -> BoxFuture<'static, ()> {
+        let (storage, key) = (self.clone(), key.to_owned());
+        Box::pin(blocking::unblock(move || {
+            if let Err(e) = storage.write(&key, &response) {
+                log::warn!("failed to cache response for {}: {}", key, e);
+            }
+        }))
+    }
+
+    fn remove(&self, key: &str) -> BoxFuture<'static, ()> {
+        let (path, key) = (self.path(key), key.to_owned());
+        Box::pin(blocking::unblock(move || match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                log::warn!("failed to remove cached response for {}: {}", key, e);
+            }
+            _ => {}
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &str) -> CachedResponse {
+        CachedResponse {
+            status: 200,
+            headers: vec![("cache-control".to_owned(), "max-age=60".to_owned())],
+            body: body.as_bytes().to_vec(),
+            request_time: UNIX_EPOCH + Duration::from_millis(1_600_000_000_250),
+            response_time: UNIX_EPOCH + Duration::from_millis(1_600_000_000_500),
+            vary: vec![("accept".to_owned(), vec!["text/html".to_owned()])],
+        }
+    }
+
+    #[async_std::test]
+    async fn memory_storage_evicts_least_recently_used() {
+        let storage = MemoryStorage::new(2);
+        storage.put("a", response("a")).await;
+        storage.put("b", response("b")).await;
+        assert!(storage.get("a").await.is_some());
+        storage.put("c", response("c")).await;
+        assert!(storage.get("b").await.is_none());
+        assert_eq!(storage.get("a").await, Some(response("a")));
+        assert_eq!(storage.get("c").await, Some(response("c")));
+        storage.remove("a").await;
+        assert!(storage.get("a").await.is_none());
+    }
+
+    #[async_std::test]
+    async fn memory_storage_is_bounded_by_size() {
+        let size = size_of(&response("a"));
+        let storage = MemoryStorage::new(10).max_size(size * 5 / 2);
+        storage.put("a", response("a")).await;
+        storage.put("b", response("b")).await;
+        assert!(storage.get("a").await.is_some());
+        storage.put("c", response("c")).await;
+        assert!(storage.get("b").await.is_none());
+        assert!(storage.get("a").await.is_some());
+        assert!(storage.get("c").await.is_some());
+
+        // A response that could never fit is not stored, and doesn't evict the others.
+        storage.put("d", response(&"d".repeat(size * 3))).await;
+        assert!(storage.get("d").await.is_none());
+        assert!(storage.get("a").await.is_some());
+        assert!(storage.get("c").await.is_some());
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("http-client-{}-{}", name, std::process::id()))
+    }
+
+    #[async_std::test]
+    async fn disk_storage_persists_responses() {
+        let dir = temp_dir("cache");
+        let storage = DiskStorage::new(&dir);
+        storage
+            .put("http://example.com/", response("\u{0}binary\u{ff}"))
+            .await;
+
+        let storage = DiskStorage::new(&dir);
+        assert_eq!(
+            storage.get("http://example.com/").await,
+            Some(response("\u{0}binary\u{ff}"))
+        );
+        assert!(storage.get("http://example.com/other").await.is_none());
+        storage.remove("http://example.com/").await;
+        assert!(storage.get("http://example.com/").await.is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[async_std::test]
+    async fn disk_storage_evicts_least_recently_used() {
+        let dir = temp_dir("cache-eviction");
+        let storage = DiskStorage::new(&dir);
+        storage.put("a", response("a")).await;
+        let size = fs::metadata(storage.path("a")).unwrap().len();
+
+        // Room for two responses of the same size, but not three.
+        let storage = storage.max_size(size * 5 / 2);
+        let pause = || async_std::task::sleep(Duration::from_millis(20));
+        pause().await;
+        storage.put("b", response("b")).await;
+        pause().await;
+        assert!(storage.get("a").await.is_some());
+        pause().await;
+        storage.put("c", response("c")).await;
+        assert!(storage.get("b").await.is_none());
+        assert!(storage.get("a").await.is_some());
+        assert!(storage.get("c").await.is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,746 @@
+//! Storing cookies and sending them back, for any `HttpClient`.
+
+use super::{Error, HttpClient, Request, Response};
+
+use futures::future::BoxFuture;
+use http_types::cookies::{Cookie, SameSite};
+use http_types::headers::{COOKIE, SET_COOKIE};
+use http_types::url::{Host, Url};
+use publicsuffix::{List, Psl};
+use serde_json::{json, Value};
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+/// The public suffix list, from <https://publicsuffix.org/list/>. See `data/README.md` for its
+/// version, and how to update it.
+const PUBLIC_SUFFIX_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+/// The public suffix list, parsed the first time it's needed.
+fn public_suffix_list() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| {
+        PUBLIC_SUFFIX_LIST
+            .parse()
+            .expect("the bundled public suffix list is valid")
+    })
+}
+
+/// The longest a cookie is kept for, whatever its `Max-Age` or `Expires` attribute says, as
+/// RFC 6265bis requires.
+const MAX_COOKIE_AGE: Duration = Duration::from_secs(400 * 24 * 60 * 60);
+
+/// The format of a cookie file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieFile {
+    /// The tab-separated format of Netscape's `cookies.txt`, as used by curl and wget.
+    Netscape,
+    /// A JSON array with an object for each cookie.
+    Json,
+}
+
+/// A client that stores the cookies set by responses, and sends them with later requests.
+///
+/// Cookies are stored following RFC 6265: their `Domain` and `Path` attributes decide which
+/// requests they're sent with, `Secure` cookies are only sent over HTTPS, and `Expires` and
+/// `Max-Age` decide how long they're kept, up to 400 days. Cookies can't be set for a suffix in
+/// the public suffix list such as `co.uk`, or with `SameSite=None` but without `Secure`. The
+/// `__Secure-` and `__Host-` name prefixes are enforced as well.
+///
+/// All clones of a jar share the same cookies, which can be saved to a file and loaded again.
+///
+/// When following redirects, wrap the `CookieJar` in a `Redirect` rather than the other way
+/// around, so that cookies are stored and sent on every hop.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::cookies::{CookieFile, CookieJar};
+/// use http_client::h1::H1Client;
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+///
+/// let client = CookieJar::new(H1Client::new()).load("cookies.txt", CookieFile::Netscape)?;
+/// client.send(Request::new(Method::Post, "https://example.com/login")).await?;
+/// client.send(Request::new(Method::Get, "https://example.com/account")).await?;
+/// client.save("cookies.txt", CookieFile::Netscape)?;
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct CookieJar<C> {
+    inner: Arc<C>,
+    cookies: Arc<Mutex<Vec<StoredCookie>>>,
+    public_suffixes: Arc<Vec<String>>,
+}
+
+impl<C> Clone for CookieJar<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            cookies: self.cookies.clone(),
+            public_suffixes: self.public_suffixes.clone(),
+        }
+    }
+}
+
+/// A cookie stored in a `CookieJar`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredCookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    expires: Option<SystemTime>,
+    created: SystemTime,
+}
+
+impl StoredCookie {
+    /// The name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The domain the cookie is sent to.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Whether the cookie is only sent to its domain, and not to its subdomains.
+    pub fn host_only(&self) -> bool {
+        self.host_only
+    }
+
+    /// The path the cookie is sent to, along with the paths below it.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether the cookie is only sent over HTTPS.
+    pub fn secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Whether the cookie was marked as unavailable to scripts.
+    pub fn http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// The `SameSite` attribute of the cookie.
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    /// When the cookie expires, or `None` for a session cookie.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, url: &Url, host: &str) -> bool {
+        let domain_matches = match self.host_only {
+            true => host == self.domain,
+            false => domain_match(host, &self.domain),
+        };
+        domain_matches
+            && path_match(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+    }
+}
+
+impl<C: HttpClient> CookieJar<C> {
+    /// Store cookies for the requests sent with `inner`, starting out empty.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            cookies: Arc::new(Mutex::new(Vec::new())),
+            public_suffixes: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Treat `suffixes` as public suffixes too, on top of those in the public suffix list, such
+    /// as `example.net` for a hosting provider that gives each customer their own subdomain.
+    pub fn public_suffixes(mut self, suffixes: impl IntoIterator<Item = String>) -> Self {
+        let suffixes = suffixes.into_iter().map(|s| s.to_ascii_lowercase());
+        Arc::make_mut(&mut self.public_suffixes).extend(suffixes);
+        self
+    }
+
+    /// Load the cookies in the file at `path`, replacing those from the same domain and path
+    /// with the same name.
+    pub fn load(self, path: impl AsRef<Path>, format: CookieFile) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let loaded = match format {
+            CookieFile::Netscape => from_netscape(&contents)?,
+            CookieFile::Json => from_json(&contents)?,
+        };
+        for cookie in loaded {
+            self.insert(cookie);
+        }
+        Ok(self)
+    }
+
+    /// Save the cookies to the file at `path`, including session cookies.
+    pub fn save(&self, path: impl AsRef<Path>, format: CookieFile) -> io::Result<()> {
+        let cookies = self.cookies();
+        let contents = match format {
+            CookieFile::Netscape => to_netscape(&cookies),
+            CookieFile::Json => serde_json::to_string_pretty(&to_json(&cookies))?,
+        };
+        fs::write(path, contents)
+    }
+
+    /// The cookies that haven't expired yet.
+    pub fn cookies(&self) -> Vec<StoredCookie> {
+        let now = SystemTime::now();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+        cookies.clone()
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    fn insert(&self, cookie: StoredCookie) {
+        let mut cookies = self.cookies.lock().unwrap();
+        let same = |c: &StoredCookie| {
+            c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path
+        };
+        let created = match cookies.iter().position(same) {
+            Some(i) => cookies.remove(i).created,
+            None => cookie.created,
+        };
+        if !cookie.is_expired(SystemTime::now()) {
+            cookies.push(StoredCookie { created, ..cookie });
+        }
+    }
+
+    /// The `Cookie` header to send with a request to `url`.
+    fn cookie_header(&self, url: &Url) -> Option<String> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut cookies: Vec<_> = self
+            .cookies()
+            .into_iter()
+            .filter(|cookie| cookie.matches(url, &host))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        // Longer paths first, then older cookies first.
+        cookies.sort_by(|a, b| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a.created.cmp(&b.created))
+        });
+        let pairs: Vec<_> = cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    /// Parse and store a `Set-Cookie` header received from `url`, if it may be stored.
+    fn store(&self, url: &Url, header: &str) {
+        match self.parse(url, header) {
+            Some(cookie) => self.insert(cookie),
+            None => log::debug!("ignoring cookie {:?} set by {}", header, url),
+        }
+    }
+
+    fn parse(&self, url: &Url, header: &str) -> Option<StoredCookie> {
+        let cookie = Cookie::parse(header).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let now = SystemTime::now();
+        let https = url.scheme() == "https";
+
+        // In whole seconds, like the times in cookie files.
+        let latest = UNIX_EPOCH + Duration::from_secs(unix_time(Some(now + MAX_COOKIE_AGE)));
+        let expires = match (cookie.max_age(), cookie.expires()) {
+            (Some(max_age), _) => {
+                let secs = Duration::from_secs(max_age.whole_seconds().max(0) as u64);
+                Some(
+                    now.checked_add(secs)
+                        .map_or(latest, |time| time.min(latest)),
+                )
+            }
+            (None, Some(expires)) => {
+                let secs = Duration::from_secs(expires.unix_timestamp().max(0) as u64);
+                Some(
+                    UNIX_EPOCH
+                        .checked_add(secs)
+                        .map_or(latest, |time| time.min(latest)),
+                )
+            }
+            (None, None) => None,
+        };
+
+        let (domain, host_only) = match cookie.domain().map(str::to_ascii_lowercase) {
+            Some(domain) if self.is_public_suffix(&domain) => match domain == host {
+                true => (host, true),
+                false => return None,
+            },
+            Some(domain) if domain_match(&host, &domain) => (domain, false),
+            Some(_) => return None,
+            None => (host, true),
+        };
+
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_owned(),
+            _ => default_path(url.path()),
+        };
+
+        let secure = cookie.secure().unwrap_or(false);
+        let same_site = cookie.same_site();
+        if secure && !https {
+            return None;
+        }
+        if same_site == Some(SameSite::None) && !secure {
+            return None;
+        }
+        let name = cookie.name();
+        if name.starts_with("__Secure-") && !secure {
+            return None;
+        }
+        if name.starts_with("__Host-") && !(secure && host_only && path == "/") {
+            return None;
+        }
+
+        Some(StoredCookie {
+            name: name.to_owned(),
+            value: cookie.value().to_owned(),
+            domain,
+            host_only,
+            path,
+            secure,
+            http_only: cookie.http_only().unwrap_or(false),
+            same_site,
+            expires,
+            created: now,
+        })
+    }
+
+    fn is_public_suffix(&self, domain: &str) -> bool {
+        // Unlisted top-level domains, such as `localhost`, are public suffixes too.
+        let listed = public_suffix_list()
+            .suffix(domain.as_bytes())
+            .is_none_or(|suffix| suffix.as_bytes() == domain.as_bytes());
+        listed || self.public_suffixes.iter().any(|suffix| suffix == domain)
+    }
+}
+
+impl<C: HttpClient> HttpClient for CookieJar<C> {
+    fn send(&self, mut req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let jar = self.clone();
+        Box::pin(async move {
+            let url = req.url().clone();
+            if let Some(cookies) = jar.cookie_header(&url) {
+                let header = match req.header(COOKIE) {
+                    Some(existing) => format!("{}; {}", existing.last().as_str(), cookies),
+                    None => cookies,
+                };
+                req.insert_header(COOKIE, header);
+            }
+
+            let res = jar.inner.send(req).await?;
+            if let Some(values) = res.header(SET_COOKIE) {
+                for value in values {
+                    jar.store(&url, value.as_str());
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    let is_ip = matches!(Host::parse(host), Ok(Host::Ipv4(_)) | Ok(Host::Ipv6(_)));
+    !is_ip
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host[..host.len() - domain.len()].ends_with('.')
+}
+
+/// Whether `path` is `cookie_path` or below it.
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The path of cookies set without one: the "directory" of the request path.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(i) => path[..i].to_owned(),
+    }
+}
+
+fn unix_time(time: Option<SystemTime>) -> u64 {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs())
+}
+
+fn from_unix_time(secs: u64) -> Option<SystemTime> {
+    match secs {
+        0 => None,
+        secs => UNIX_EPOCH.checked_add(Duration::from_secs(secs)),
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn to_netscape(cookies: &[StoredCookie]) -> String {
+    let mut file = String::from("# Netscape HTTP Cookie File\n");
+    for cookie in cookies {
+        let bool = |b| if b { "TRUE" } else { "FALSE" };
+        file.push_str(&format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if cookie.http_only { "#HttpOnly_" } else { "" },
+            if cookie.host_only { "" } else { "." },
+            cookie.domain,
+            bool(!cookie.host_only),
+            cookie.path,
+            bool(cookie.secure),
+            unix_time(cookie.expires),
+            cookie.name,
+            cookie.value,
+        ));
+    }
+    file
+}
+
+fn from_netscape(file: &str) -> io::Result<Vec<StoredCookie>> {
+    let mut cookies = Vec::new();
+    for (i, line) in file.lines().enumerate() {
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = line.split('\t').collect();
+        let invalid = || invalid(format!("invalid cookie on line {}", i + 1));
+        if fields.len() != 7 {
+            return Err(invalid());
+        }
+        let host_only = match fields[1] {
+            "TRUE" => false,
+            "FALSE" => true,
+            _ => return Err(invalid()),
+        };
+        cookies.push(StoredCookie {
+            name: fields[5].to_owned(),
+            value: fields[6].to_owned(),
+            domain: fields[0].trim_start_matches('.').to_ascii_lowercase(),
+            host_only,
+            path: fields[2].to_owned(),
+            secure: fields[3] == "TRUE",
+            http_only,
+            same_site: None,
+            expires: from_unix_time(fields[4].parse().map_err(|_| invalid())?),
+            created: SystemTime::now(),
+        });
+    }
+    Ok(cookies)
+}
+
+fn to_json(cookies: &[StoredCookie]) -> Value {
+    cookies
+        .iter()
+        .map(|cookie| {
+            json!({
+                "name": cookie.name,
+                "value": cookie.value,
+                "domain": cookie.domain,
+                "host_only": cookie.host_only,
+                "path": cookie.path,
+                "secure": cookie.secure,
+                "http_only": cookie.http_only,
+                "same_site": cookie.same_site.map(|s| s.to_string()),
+                "expires": cookie.expires.map(|expires| unix_time(Some(expires))),
+            })
+        })
+        .collect()
+}
+
+fn from_json(file: &str) -> io::Result<Vec<StoredCookie>> {
+    let json: Value = serde_json::from_str(file)?;
+    let cookies = json
+        .as_array()
+        .ok_or_else(|| invalid("expected an array of cookies".to_owned()))?;
+    cookies
+        .iter()
+        .enumerate()
+        .map(|(i, cookie)| {
+            let parse = || {
+                let string = |key: &str| cookie[key].as_str().map(str::to_owned);
+                let same_site = match cookie["same_site"].as_str() {
+                    Some("Strict") => Some(SameSite::Strict),
+                    Some("Lax") => Some(SameSite::Lax),
+                    Some("None") => Some(SameSite::None),
+                    _ => None,
+                };
+                Some(StoredCookie {
+                    name: string("name")?,
+                    value: string("value")?,
+                    domain: string("domain")?.to_ascii_lowercase(),
+                    host_only: cookie["host_only"].as_bool()?,
+                    path: string("path")?,
+                    secure: cookie["secure"].as_bool().unwrap_or(false),
+                    http_only: cookie["http_only"].as_bool().unwrap_or(false),
+                    same_site,
+                    expires: cookie["expires"].as_u64().and_then(from_unix_time),
+                    created: SystemTime::now(),
+                })
+            };
+            parse().ok_or_else(|| invalid(format!("invalid cookie at index {}", i)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+    use http_types::{Method, StatusCode};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn jar() -> CookieJar<MockClient> {
+        CookieJar::new(MockClient::new())
+    }
+
+    fn names(jar: &CookieJar<MockClient>, url: &str) -> Option<String> {
+        jar.cookie_header(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn matches_domains_and_paths() {
+        let jar = jar();
+        let url = Url::parse("https://www.example.com/account/settings").unwrap();
+        for header in &[
+            "host=1",
+            "domain=2; Domain=.Example.com",
+            "root=3; Path=/",
+            "secure=4; Secure; Path=/",
+            "deep=5; Path=/account/settings/more",
+        ] {
+            jar.store(&url, header);
+        }
+
+        assert_eq!(
+            names(&jar, "https://www.example.com/account/settings").as_deref(),
+            Some("host=1; domain=2; root=3; secure=4")
+        );
+        assert_eq!(
+            names(&jar, "https://api.example.com/account").as_deref(),
+            Some("domain=2")
+        );
+        assert_eq!(
+            names(&jar, "http://www.example.com/").as_deref(),
+            Some("root=3")
+        );
+        assert_eq!(
+            names(&jar, "https://www.example.com/accounts").as_deref(),
+            Some("root=3; secure=4")
+        );
+        assert_eq!(names(&jar, "https://example.org/"), None);
+    }
+
+    #[test]
+    fn rejects_cookies_it_may_not_store() {
+        let jar = jar().public_suffixes(vec!["hosting.test".to_owned()]);
+        let stored = |url: &str, header: &str| {
+            jar.clear();
+            jar.store(&Url::parse(url).unwrap(), header);
+            !jar.cookies().is_empty()
+        };
+        assert!(!stored("https://www.example.com/", "a=1; Domain=com"));
+        assert!(!stored("https://www.example.co.uk/", "a=1; Domain=co.uk"));
+        assert!(!stored(
+            "https://a.b.kawasaki.jp/",
+            "a=1; Domain=b.kawasaki.jp"
+        ));
+        assert!(!stored(
+            "https://shop.myshopify.com/",
+            "a=1; Domain=myshopify.com"
+        ));
+        assert!(!stored("http://app.localhost/", "a=1; Domain=localhost"));
+        assert!(stored(
+            "https://www.city.kawasaki.jp/",
+            "a=1; Domain=city.kawasaki.jp"
+        ));
+        assert!(!stored(
+            "https://me.hosting.test/",
+            "a=1; Domain=hosting.test"
+        ));
+        assert!(!stored("https://www.example.com/", "a=1; Domain=other.com"));
+        assert!(!stored(
+            "https://example.com/",
+            "a=1; Domain=www.example.com"
+        ));
+        assert!(!stored("http://example.com/", "a=1; Secure"));
+        assert!(!stored("https://example.com/", "a=1; SameSite=None"));
+        assert!(!stored("https://example.com/", "__Secure-a=1"));
+        assert!(!stored(
+            "https://example.com/",
+            "__Host-a=1; Secure; Path=/; Domain=example.com"
+        ));
+        assert!(stored("https://example.com/", "__Host-a=1; Secure; Path=/"));
+        assert!(stored("https://example.com/", "a=1; SameSite=None; Secure"));
+        assert!(stored("http://127.0.0.1/", "a=1"));
+    }
+
+    #[test]
+    fn caps_how_long_cookies_are_kept() {
+        let jar = jar();
+        let url = Url::parse("https://example.com/").unwrap();
+        jar.store(&url, "a=1; Max-Age=9223372036854775807");
+        jar.store(&url, "b=2; Expires=Fri, 31 Dec 9999 23:59:59 GMT");
+        jar.store(&url, "c=3; Max-Age=60");
+
+        let latest = SystemTime::now() + MAX_COOKIE_AGE;
+        let cookies = jar.cookies();
+        assert_eq!(cookies.len(), 3);
+        for cookie in &cookies[..2] {
+            let expires = cookie.expires().unwrap();
+            assert!(expires <= latest);
+            assert!(expires > latest - Duration::from_secs(60));
+        }
+        assert!(cookies[2].expires().unwrap() < SystemTime::now() + Duration::from_secs(61));
+    }
+
+    #[async_std::test]
+    async fn stores_and_sends_cookies() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Post, "/login").respond_with(|_| {
+            let mut res = Response::new(StatusCode::Ok);
+            res.append_header(SET_COOKIE, "session=abc; Path=/; HttpOnly");
+            res.append_header(SET_COOKIE, "theme=dark; Max-Age=3600");
+            res.append_header(SET_COOKIE, "old=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT");
+            res
+        });
+        mock.expect(Method::Get, "/logout").respond_with(|_| {
+            let mut res = Response::new(StatusCode::Ok);
+            res.append_header(SET_COOKIE, "session=; Path=/; Max-Age=0");
+            res
+        });
+        mock.expect(Method::Get, "/account")
+            .header("cookie", "lang=en; session=abc; theme=dark")
+            .times(1)
+            .respond(StatusCode::Ok, "");
+        mock.expect(Method::Get, "/account")
+            .header("cookie", "theme=dark")
+            .times(1)
+            .respond(StatusCode::Ok, "");
+        let jar = CookieJar::new(mock.clone());
+
+        let url = "https://example.com";
+        jar.send(Request::new(
+            Method::Post,
+            format!("{}/login", url).as_str(),
+        ))
+        .await?;
+        let cookies = jar.cookies();
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies[0].http_only() && cookies[0].expires().is_none());
+        assert!(cookies[1].expires().unwrap() > SystemTime::now());
+
+        let mut req = Request::new(Method::Get, format!("{}/account", url).as_str());
+        req.insert_header(COOKIE, "lang=en");
+        jar.send(req).await?;
+        jar.send(Request::new(
+            Method::Get,
+            format!("{}/logout", url).as_str(),
+        ))
+        .await?;
+        jar.send(Request::new(
+            Method::Get,
+            format!("{}/account", url).as_str(),
+        ))
+        .await?;
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn saves_and_loads_cookie_files() -> io::Result<()> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let jar = jar();
+        let url = Url::parse("https://www.example.com/app/index.html").unwrap();
+        jar.store(&url, "session=abc; HttpOnly; Secure");
+        jar.store(&url, "theme=dark; Domain=example.com; Path=/; Expires=Wed, 21 Oct 2099 07:28:00 GMT; SameSite=Strict");
+
+        for format in [CookieFile::Netscape, CookieFile::Json].iter() {
+            let n = COUNT.fetch_add(1, Ordering::SeqCst);
+            let path = std::env::temp_dir().join(format!(
+                "http-client-cookies-{}-{}",
+                std::process::id(),
+                n
+            ));
+            jar.save(&path, *format)?;
+            let loaded = CookieJar::new(MockClient::new()).load(&path, *format)?;
+            fs::remove_file(&path)?;
+
+            let strip = |cookies: Vec<StoredCookie>| -> Vec<_> {
+                cookies
+                    .into_iter()
+                    .map(|cookie| StoredCookie {
+                        created: UNIX_EPOCH,
+                        same_site: cookie.same_site.filter(|_| *format == CookieFile::Json),
+                        ..cookie
+                    })
+                    .collect()
+            };
+            assert_eq!(
+                strip(loaded.cookies()),
+                strip(jar.cookies()),
+                "{:?}",
+                format
+            );
+        }
+
+        // The 2099 expiry is capped to 400 days from now.
+        let cookies = jar.cookies();
+        let expires = unix_time(cookies[1].expires());
+        assert!(expires < unix_time(Some(SystemTime::now() + MAX_COOKIE_AGE)) + 1);
+        assert_eq!(
+            to_netscape(&cookies),
+            format!(
+                "# Netscape HTTP Cookie File\n\
+                 #HttpOnly_www.example.com\tFALSE\t/app\tTRUE\t0\tsession\tabc\n\
+                 .example.com\tTRUE\t/\tFALSE\t{}\ttheme\tdark\n",
+                expires
+            )
+        );
+        Ok(())
+    }
+}
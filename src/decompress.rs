@@ -0,0 +1,418 @@
+//! Decompressing response bodies, for any `HttpClient`.
+
+use super::{Body, Error, HttpClient, Request, Response};
+
+#[cfg(feature = "decompress_brotli")]
+use async_compression::futures::bufread::BrotliDecoder;
+#[cfg(feature = "decompress_zstd")]
+use async_compression::futures::bufread::ZstdDecoder;
+use async_compression::futures::bufread::{DeflateDecoder, GzipDecoder, ZlibDecoder};
+use futures::future::BoxFuture;
+use futures::io::{AsyncBufRead, AsyncRead, BufReader};
+use futures::ready;
+use http_types::headers::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use http_types::{Method, StatusCode};
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// A function that decodes a body in some content encoding.
+pub type DecodeFn = dyn Fn(Body) -> Body + Send + Sync + 'static;
+
+type Decoders = Vec<(String, Arc<DecodeFn>)>;
+
+/// A client that asks for compressed responses, and decompresses them.
+///
+/// Requests are sent with an `Accept-Encoding` header listing the supported encodings, and
+/// responses in any of them are decoded while their body is read. Decoded responses lose their
+/// `Content-Encoding` and `Content-Length` headers, since those describe the encoded body.
+///
+/// `gzip` and `deflate` are supported out of the box, and `br` and `zstd` with the
+/// `decompress_brotli` and `decompress_zstd` features. Other encodings can be added with a decoder
+/// from another crate using `decoder`.
+///
+/// Decompression is skipped for requests that already have an `Accept-Encoding` header, or that
+/// carry the `SkipDecompression` extension; their responses are returned as they are, as are
+/// responses known to have an empty body.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::decompress::Decompress;
+/// use http_client::h1::H1Client;
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+///
+/// let client = Decompress::new(H1Client::new());
+/// let mut res = client.send(Request::new(Method::Get, "http://example.com")).await?;
+/// println!("{}", res.body_string().await?);
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+pub struct Decompress<C> {
+    inner: Arc<C>,
+    decoders: Arc<Decoders>,
+}
+
+impl<C: fmt::Debug> fmt::Debug for Decompress<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encodings: Vec<_> = self.decoders.iter().map(|(name, _)| name).collect();
+        f.debug_struct("Decompress")
+            .field("inner", &self.inner)
+            .field("encodings", &encodings)
+            .finish()
+    }
+}
+
+impl<C> Clone for Decompress<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            decoders: self.decoders.clone(),
+        }
+    }
+}
+
+/// A request extension that turns decompression off for that request.
+///
+/// # Examples
+///
+/// ```
+/// use http_client::decompress::SkipDecompression;
+/// use http_types::{Method, Request};
+///
+/// let mut req = Request::new(Method::Get, "http://example.com/archive.tar.gz");
+/// req.ext_mut().insert(SkipDecompression);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkipDecompression;
+
+impl<C: HttpClient> Decompress<C> {
+    /// Decompress the responses to requests sent with `inner`.
+    pub fn new(inner: C) -> Self {
+        let gzip: Arc<DecodeFn> = Arc::new(|body| decoded(body, GzipDecoder::new));
+        let deflate: Arc<DecodeFn> = Arc::new(|body| decoded(body, Deflate::new));
+        #[allow(unused_mut)]
+        let mut decoders: Decoders = vec![
+            ("gzip".to_owned(), gzip.clone()),
+            ("x-gzip".to_owned(), gzip),
+            ("deflate".to_owned(), deflate),
+        ];
+        #[cfg(feature = "decompress_brotli")]
+        decoders.push((
+            "br".to_owned(),
+            Arc::new(|body| decoded(body, BrotliDecoder::new)),
+        ));
+        #[cfg(feature = "decompress_zstd")]
+        decoders.push((
+            "zstd".to_owned(),
+            Arc::new(|body| decoded(body, ZstdDecoder::new)),
+        ));
+        Self {
+            inner: Arc::new(inner),
+            decoders: Arc::new(decoders),
+        }
+    }
+
+    /// Support the content encoding `encoding`, decoding bodies with `decode`.
+    ///
+    /// `decode` gets the encoded body, and returns a body that decodes it as it's read.
+    pub fn decoder<F>(mut self, encoding: &str, decode: F) -> Self
+    where
+        F: Fn(Body) -> Body + Send + Sync + 'static,
+    {
+        let encoding = encoding.to_ascii_lowercase();
+        let decoders = Arc::make_mut(&mut self.decoders);
+        decoders.retain(|(name, _)| *name != encoding);
+        decoders.push((encoding, Arc::new(decode)));
+        self
+    }
+
+    fn accept_encoding(&self) -> String {
+        let encodings: Vec<_> = self
+            .decoders
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| *name != "x-gzip")
+            .collect();
+        encodings.join(", ")
+    }
+}
+
+impl<C: HttpClient> HttpClient for Decompress<C> {
+    fn send(&self, mut req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let client = self.clone();
+        Box::pin(async move {
+            let skip = req.ext().get::<SkipDecompression>().is_some()
+                || req.header(ACCEPT_ENCODING).is_some();
+            if skip {
+                return client.inner.send(req).await;
+            }
+            req.insert_header(ACCEPT_ENCODING, client.accept_encoding());
+            let head = req.method() == Method::Head;
+
+            let mut res = client.inner.send(req).await?;
+            // Some servers label empty bodies with the encoding they'd have used.
+            let no_body = res.len() == Some(0)
+                || matches!(
+                    res.status(),
+                    StatusCode::NoContent | StatusCode::NotModified
+                );
+            if !head && !no_body {
+                client.decompress(&mut res);
+            }
+            Ok(res)
+        })
+    }
+}
+
+impl<C: HttpClient> Decompress<C> {
+    /// Decode the body of `res`, if it's in encodings that are all supported.
+    fn decompress(&self, res: &mut Response) {
+        let encodings: Vec<String> = match res.header(CONTENT_ENCODING) {
+            Some(values) => values
+                .iter()
+                .flat_map(|value| value.as_str().split(','))
+                .map(|encoding| encoding.trim().to_ascii_lowercase())
+                .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+                .collect(),
+            None => return,
+        };
+        let mut decoders = Vec::new();
+        for encoding in &encodings {
+            match self.decoders.iter().find(|(name, _)| name == encoding) {
+                Some((_, decode)) => decoders.push(decode.clone()),
+                None => {
+                    log::debug!("not decoding unsupported content encoding {:?}", encoding);
+                    return;
+                }
+            }
+        }
+
+        // Encodings are listed in the order they were applied.
+        let mut body = res.take_body();
+        for decode in decoders.iter().rev() {
+            body = decode(body);
+        }
+        res.remove_header(CONTENT_ENCODING);
+        res.remove_header(CONTENT_LENGTH);
+        res.set_body(body);
+    }
+}
+
+/// A body decoding `body` as it's read, with the decoder `decoder` creates.
+fn decoded<D>(body: Body, decoder: impl FnOnce(Body) -> D) -> Body
+where
+    D: AsyncRead + Send + Sync + Unpin + 'static,
+{
+    let mime = body.mime().clone();
+    let mut body = Body::from_reader(BufReader::new(decoder(body)), None);
+    body.set_mime(mime);
+    body
+}
+
+/// A decoder for the `deflate` encoding.
+///
+/// `deflate` is meant to be the zlib format, but some servers send raw DEFLATE data instead, so
+/// the first bytes of the body decide which one it's decoded as.
+enum Deflate {
+    Sniffing(Option<Body>),
+    Zlib(ZlibDecoder<Body>),
+    Raw(DeflateDecoder<Body>),
+}
+
+impl Deflate {
+    fn new(body: Body) -> Self {
+        Deflate::Sniffing(Some(body))
+    }
+}
+
+impl AsyncRead for Deflate {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            match &mut *self {
+                Deflate::Sniffing(body) => {
+                    let header = ready!(Pin::new(body.as_mut().unwrap()).poll_fill_buf(cx))?;
+                    // A zlib header names DEFLATE as its method, and is a multiple of 31.
+                    let zlib = header.len() >= 2
+                        && header[0] & 0x0f == 8
+                        && u16::from_be_bytes([header[0], header[1]]) % 31 == 0;
+                    let body = body.take().unwrap();
+                    *self = if zlib {
+                        Deflate::Zlib(ZlibDecoder::new(body))
+                    } else {
+                        Deflate::Raw(DeflateDecoder::new(body))
+                    };
+                }
+                Deflate::Zlib(decoder) => return Pin::new(decoder).poll_read(cx, buf),
+                Deflate::Raw(decoder) => return Pin::new(decoder).poll_read(cx, buf),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+
+    const LINES_GZ: &[u8] = include_bytes!("../tests/fixtures/lines.gz");
+
+    fn lines() -> String {
+        (0..1000)
+            .map(|i| format!("{}: the quick brown fox jumps over the lazy dog\n", i % 10))
+            .collect()
+    }
+
+    fn adler32(bytes: &[u8]) -> u32 {
+        let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+            let a = (a + byte as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        b << 16 | a
+    }
+
+    async fn decode(encoding: &str, encoded: Vec<u8>) -> http_types::Result<String> {
+        let encoding = encoding.to_owned();
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/").respond_with(move |_| {
+            let mut res = Response::new(StatusCode::Ok);
+            res.insert_header(CONTENT_ENCODING, encoding.as_str());
+            res.set_body(encoded.clone());
+            res
+        });
+        let client = Decompress::new(mock);
+        let mut res = client
+            .send(Request::new(Method::Get, "http://api.test/"))
+            .await?;
+        res.body_string().await
+    }
+
+    fn gzipped(encoding: &'static str) -> impl Fn(&crate::mock::ReceivedRequest) -> Response {
+        move |_| {
+            let mut res = Response::new(StatusCode::Ok);
+            res.insert_header(CONTENT_ENCODING, encoding);
+            res.set_body(LINES_GZ);
+            res.set_content_type(http_types::mime::PLAIN);
+            res
+        }
+    }
+
+    #[async_std::test]
+    async fn decodes_supported_encodings() -> http_types::Result<()> {
+        let mut accepted = vec!["gzip", "deflate"];
+        if cfg!(feature = "decompress_brotli") {
+            accepted.push("br");
+        }
+        if cfg!(feature = "decompress_zstd") {
+            accepted.push("zstd");
+        }
+        accepted.push("compress");
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/")
+            .header("accept-encoding", &accepted.join(", "))
+            .respond_with(gzipped("gzip"));
+        let client = Decompress::new(mock.clone()).decoder("compress", |body| body);
+
+        let mut res = client
+            .send(Request::new(Method::Get, "http://api.test/"))
+            .await?;
+        assert!(res.header(CONTENT_ENCODING).is_none());
+        assert!(res.header(CONTENT_LENGTH).is_none());
+        assert_eq!(res.content_type(), Some(http_types::mime::PLAIN));
+        let body = res.body_string().await?;
+        assert_eq!(body.lines().count(), 1000);
+        assert!(body.starts_with("0: the quick brown fox"));
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn leaves_other_responses_alone() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/unsupported")
+            .respond_with(gzipped("gzip, compress"));
+        mock.expect(Method::Get, "/skipped")
+            .respond_with(gzipped("gzip"));
+        mock.expect(Method::Head, "/").respond_with(gzipped("gzip"));
+        mock.expect(Method::Get, "/empty").respond_with(|_| {
+            let mut res = Response::new(StatusCode::Ok);
+            res.insert_header(CONTENT_ENCODING, "gzip");
+            res.set_body(Body::empty());
+            res
+        });
+        let client = Decompress::new(mock.clone());
+
+        let req = Request::new(Method::Get, "http://api.test/unsupported");
+        let mut res = client.send(req).await?;
+        assert_eq!(res[CONTENT_ENCODING], "gzip, compress");
+        assert_eq!(res.body_bytes().await?, LINES_GZ);
+
+        let mut req = Request::new(Method::Get, "http://api.test/skipped");
+        req.ext_mut().insert(SkipDecompression);
+        let mut res = client.send(req).await?;
+        assert_eq!(res.body_bytes().await?, LINES_GZ);
+        assert!(mock.received()[1].header("accept-encoding").is_empty());
+
+        let res = client
+            .send(Request::new(Method::Head, "http://api.test/"))
+            .await?;
+        assert_eq!(res[CONTENT_ENCODING], "gzip");
+
+        let req = Request::new(Method::Get, "http://api.test/empty");
+        let mut res = client.send(req).await?;
+        assert_eq!(res[CONTENT_ENCODING], "gzip");
+        assert_eq!(res.body_string().await?, "");
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn decodes_zlib_and_raw_deflate() -> http_types::Result<()> {
+        // The DEFLATE data in the gzip fixture, raw and in the zlib format.
+        let raw = LINES_GZ[10..LINES_GZ.len() - 8].to_vec();
+        assert!(decode("deflate", raw.clone()).await? == lines());
+        let mut zlib = vec![0x78, 0xda];
+        zlib.extend(&raw);
+        zlib.extend(&adler32(lines().as_bytes()).to_be_bytes());
+        assert!(decode("deflate", zlib).await? == lines());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn fails_to_read_corrupt_bodies() {
+        let mut corrupt = LINES_GZ.to_vec();
+        let len = corrupt.len();
+        corrupt[len - 6] ^= 1;
+        assert!(decode("gzip", corrupt).await.is_err());
+        assert!(decode("gzip", LINES_GZ[..100].to_vec()).await.is_err());
+        assert!(decode("gzip", b"not gzip".to_vec()).await.is_err());
+    }
+
+    #[cfg(feature = "decompress_brotli")]
+    #[async_std::test]
+    async fn decodes_brotli() -> http_types::Result<()> {
+        let encoded = include_bytes!("../tests/fixtures/lines.br");
+        assert!(decode("br", encoded.to_vec()).await? == lines());
+        Ok(())
+    }
+
+    #[cfg(feature = "decompress_zstd")]
+    #[async_std::test]
+    async fn decodes_zstd() -> http_types::Result<()> {
+        let encoded = include_bytes!("../tests/fixtures/lines.zst");
+        assert!(decode("zstd", encoded.to_vec()).await? == lines());
+        Ok(())
+    }
+}
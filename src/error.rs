@@ -0,0 +1,265 @@
+//! What went wrong when sending a request.
+//!
+//! Every error returned by the `send` method of the clients in this crate carries an `ErrorKind`,
+//! telling apart failures to resolve the host, to connect, to secure the connection, and so on.
+//! The kind is attached by wrapping the underlying error in a `SendError`, which can be reached by
+//! downcasting the `http_types::Error`:
+//!
+//! ```no_run
+//! # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+//! # #[async_std::main]
+//! # async fn main() -> Result<(), http_types::Error> {
+//! use http_client::error::{ErrorKind, SendError};
+//! use http_client::h1::H1Client;
+//! use http_client::HttpClient;
+//! use http_types::{Method, Request};
+//!
+//! let client = H1Client::new();
+//! match client.send(Request::new(Method::Get, "http://example.invalid")).await {
+//!     Ok(_) => println!("found"),
+//!     Err(err) if ErrorKind::of(&err) == ErrorKind::Dns => println!("no such host"),
+//!     Err(err) => {
+//!         // The error the backend ran into is the source of the `SendError`.
+//!         if let Some(err) = err.downcast_ref::<SendError>() {
+//!             println!("{}: {:?}", err.kind(), err.get_ref());
+//!         }
+//!         return Err(err);
+//!     }
+//! }
+//! # Ok(()) }
+//! # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+//! # fn main() {}
+//! ```
+//!
+//! The error the backend ran into, such as an `io::Error` or an `h1::TimeoutError`, is the
+//! `source` of the `SendError`, so the whole chain of causes can be walked from
+//! `AsRef<dyn std::error::Error>` on the `http_types::Error`.
+
+use super::Error;
+
+use http_types::StatusCode;
+
+use std::error::Error as StdError;
+use std::fmt;
+
+/// The kind of failure behind an error returned by `HttpClient::send`.
+///
+/// Errors with a kind have a status code that depends on it only: `400 Bad Request` for
+/// `InvalidRequest`, `504 Gateway Timeout` for `Timeout`, `500 Internal Server Error` for `Other`,
+/// and `502 Bad Gateway` for the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The host name could not be resolved.
+    Dns,
+    /// No connection could be established, or it broke before the response started, including
+    /// through a proxy.
+    Connect,
+    /// The connection could not be secured with TLS, or TLS is misconfigured.
+    Tls,
+    /// A configured timeout elapsed.
+    Timeout,
+    /// The server's response was not valid HTTP.
+    Protocol,
+    /// The request body could not be sent.
+    Body,
+    /// The request cannot be sent, such as for a URL without a host or an invalid header.
+    InvalidRequest,
+    /// The request was cancelled before it completed.
+    Cancelled,
+    /// Anything else.
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind of `err`, or `Other` if it has none.
+    pub fn of(err: &Error) -> Self {
+        match err.downcast_ref::<SendError>() {
+            Some(err) => err.kind(),
+            None => ErrorKind::Other,
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            ErrorKind::InvalidRequest => StatusCode::BadRequest,
+            ErrorKind::Timeout => StatusCode::GatewayTimeout,
+            ErrorKind::Other => StatusCode::InternalServerError,
+            _ => StatusCode::BadGateway,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            ErrorKind::Dns => "DNS error",
+            ErrorKind::Connect => "connection error",
+            ErrorKind::Tls => "TLS error",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Protocol => "protocol error",
+            ErrorKind::Body => "body error",
+            ErrorKind::InvalidRequest => "invalid request",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Other => "other error",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// An error with the `ErrorKind` it is of.
+///
+/// Displays as the error it wraps, which is also its `source`.
+#[derive(Debug)]
+pub struct SendError {
+    kind: ErrorKind,
+    error: Error,
+}
+
+impl SendError {
+    /// Wrap `error`, of kind `kind`.
+    pub fn new<E>(kind: ErrorKind, error: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Self {
+            kind,
+            error: Error::new(kind.status(), error),
+        }
+    }
+
+    /// The kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The wrapped error.
+    pub fn get_ref(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        self.error.as_ref()
+    }
+
+    /// Unwrap the error.
+    pub fn into_inner(self) -> Error {
+        self.error
+    }
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl StdError for SendError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.get_ref())
+    }
+}
+
+// The helpers below are only compiled for the backends that use them.
+
+/// Give `err` the kind `kind`, unless it has one already.
+///
+/// Kinds are attached where the failure is best understood, so the first one sticks.
+#[cfg_attr(
+    not(any(
+        feature = "h1_client_native_tls",
+        feature = "h1_client_rustls",
+        feature = "hyper_client",
+        all(feature = "curl_client", not(target_arch = "wasm32")),
+        all(feature = "wasm_client", target_arch = "wasm32")
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn with_kind(err: Error, kind: ErrorKind) -> Error {
+    if err.downcast_ref::<SendError>().is_some() {
+        return err;
+    }
+    Error::new(kind.status(), SendError { kind, error: err })
+}
+
+/// An error of kind `kind`, with the message `msg`.
+#[cfg(any(
+    feature = "h1_client_native_tls",
+    feature = "h1_client_rustls",
+    feature = "hyper_client",
+    all(feature = "wasm_client", target_arch = "wasm32")
+))]
+pub(crate) fn kind_error(kind: ErrorKind, msg: impl Into<String>) -> Error {
+    with_kind(Error::from_str(kind.status(), msg.into()), kind)
+}
+
+/// Attaching an `ErrorKind` to the error of a `Result`.
+#[cfg(any(
+    feature = "h1_client_native_tls",
+    feature = "h1_client_rustls",
+    feature = "hyper_client",
+    all(feature = "curl_client", not(target_arch = "wasm32"))
+))]
+pub(crate) trait ResultExt<T> {
+    /// Give the error the kind `kind`, unless it has one already.
+    fn error_kind(self, kind: ErrorKind) -> Result<T, Error>;
+
+    /// Give the error the kind `Connect` or `Timeout` if it's an I/O error saying the connection
+    /// broke or timed out, and `kind` otherwise.
+    #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+    fn io_error_kind(self, kind: ErrorKind) -> Result<T, Error>;
+}
+
+#[cfg(any(
+    feature = "h1_client_native_tls",
+    feature = "h1_client_rustls",
+    feature = "hyper_client",
+    all(feature = "curl_client", not(target_arch = "wasm32"))
+))]
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn error_kind(self, kind: ErrorKind) -> Result<T, Error> {
+        self.map_err(|err| with_kind(err.into(), kind))
+    }
+
+    #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+    fn io_error_kind(self, kind: ErrorKind) -> Result<T, Error> {
+        use std::io;
+
+        self.map_err(|err| {
+            let err = err.into();
+            let kind = match err.downcast_ref::<io::Error>().map(io::Error::kind) {
+                Some(io::ErrorKind::TimedOut) => ErrorKind::Timeout,
+                Some(
+                    io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe,
+                ) => ErrorKind::Connect,
+                _ => kind,
+            };
+            with_kind(err, kind)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn attaches_kinds_once() {
+        let cause = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+        let err = Error::new(StatusCode::BadRequest, cause);
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Other);
+
+        let err = with_kind(err, ErrorKind::Connect);
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Connect);
+        assert_eq!(err.status(), StatusCode::BadGateway);
+        assert_eq!(err.to_string(), "refused");
+
+        let err = with_kind(err, ErrorKind::Timeout);
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Connect);
+        let err: &(dyn StdError + 'static) = err.as_ref();
+        let source = err.source().unwrap();
+        let io = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,875 @@
+//! http-client implementation for async-h1.
+
+use super::{Body, Error, HttpClient, Request, Response};
+use crate::error::{kind_error, ErrorKind, ResultExt};
+
+use async_h1::client;
+use futures::future::BoxFuture;
+use http_types::headers::{CONNECTION, HOST, PROXY_AUTHORIZATION};
+use http_types::url::Host;
+use http_types::Version;
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod pool;
+mod proxy;
+mod resolve;
+mod runtime;
+mod socks;
+mod tcp;
+mod timeout;
+mod tls;
+mod unix;
+
+use pool::{Checkout, Conn, Pool, PoolKey, ReleaseOnEof};
+use proxy::{AbsoluteForm, ProxyKind};
+use timeout::timeout;
+use tls::TlsConnector;
+
+pub use pool::PoolConfig;
+pub use proxy::Proxy;
+pub use resolve::{CachingResolver, DefaultResolver, Lookup, Resolver, StaticResolver};
+#[cfg(feature = "h1_async_std")]
+pub use runtime::AsyncStdRuntime;
+#[cfg(feature = "h1_smol")]
+pub use runtime::SmolRuntime;
+#[cfg(feature = "h1_tokio")]
+pub use runtime::TokioRuntime;
+pub use runtime::{Connection, Runtime, Stream};
+pub use timeout::{TimeoutError, TimeoutKind};
+pub use tls::{Certificate, Identity, TlsConfig, TlsVersion};
+
+/// Async-h1 based HTTP Client.
+///
+/// Connections are kept alive and reused for later requests to the same origin. The pool of
+/// idle connections is shared between clones of a client.
+///
+/// Connections are opened with async-std, unless the `h1_async_std` feature is disabled or
+/// another `Runtime` is given to the builder. Without it, `TokioRuntime` is used if `h1_tokio` is
+/// enabled, in which case requests must be sent from within a tokio runtime, and `SmolRuntime`
+/// otherwise.
+#[derive(Debug, Clone)]
+pub struct H1Client {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    pool: Arc<Pool>,
+    resolver: Box<dyn Resolver>,
+    runtime: Box<dyn Runtime>,
+    tls: TlsConnector,
+    proxies: Vec<Proxy>,
+    unix_socket: Option<PathBuf>,
+    connect_timeout: Option<Duration>,
+    tls_handshake_timeout: Option<Duration>,
+    first_byte_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+}
+
+impl Default for H1Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl H1Client {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    /// Create a builder to configure a new instance.
+    pub fn builder() -> H1ClientBuilder {
+        H1ClientBuilder::new()
+    }
+
+    /// Create a new instance with a custom connection pool configuration.
+    pub fn with_pool_config(config: PoolConfig) -> Self {
+        Self::builder().pool_config(config).build()
+    }
+
+    /// Create a new instance that looks up host names using `resolver`.
+    pub fn with_resolver(resolver: impl Resolver) -> Self {
+        Self::builder().resolver(resolver).build()
+    }
+}
+
+/// A builder for `H1Client`.
+///
+/// No timeouts are set by default.
+///
+/// # Examples
+///
+/// ```
+/// use http_client::h1::H1Client;
+/// use std::time::Duration;
+///
+/// let client = H1Client::builder()
+///     .connect_timeout(Duration::from_secs(5))
+///     .request_timeout(Duration::from_secs(30))
+///     .build();
+/// ```
+#[derive(Debug)]
+pub struct H1ClientBuilder {
+    pool_config: PoolConfig,
+    resolver: Box<dyn Resolver>,
+    runtime: Box<dyn Runtime>,
+    tls_config: TlsConfig,
+    proxies: Vec<Proxy>,
+    unix_socket: Option<PathBuf>,
+    connect_timeout: Option<Duration>,
+    tls_handshake_timeout: Option<Duration>,
+    first_byte_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+}
+
+impl Default for H1ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl H1ClientBuilder {
+    /// Create a new builder with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            pool_config: PoolConfig::default(),
+            resolver: Box::new(DefaultResolver::new()),
+            runtime: runtime::default_runtime(),
+            tls_config: TlsConfig::default(),
+            proxies: Vec::new(),
+            unix_socket: None,
+            connect_timeout: None,
+            tls_handshake_timeout: None,
+            first_byte_timeout: None,
+            request_timeout: None,
+        }
+    }
+
+    /// Configure the pool of idle connections.
+    pub fn pool_config(mut self, config: PoolConfig) -> Self {
+        self.pool_config = config;
+        self
+    }
+
+    /// Look up host names using `resolver`.
+    pub fn resolver(mut self, resolver: impl Resolver) -> Self {
+        self.resolver = Box::new(resolver);
+        self
+    }
+
+    /// Open connections and wait for timeouts with `runtime`, instead of with the default one.
+    ///
+    /// Use the runtime of the executor requests are sent from, so that a client doesn't need a
+    /// runtime of its own.
+    pub fn runtime(mut self, runtime: impl Runtime) -> Self {
+        self.runtime = Box::new(runtime);
+        self
+    }
+
+    /// Configure how `https` connections are secured.
+    pub fn tls_config(mut self, config: TlsConfig) -> Self {
+        self.tls_config = config;
+        self
+    }
+
+    /// Send requests through `proxy`.
+    ///
+    /// When several proxies are added, each request goes through the first one that applies to
+    /// it.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Send requests through the proxies configured by the `HTTP_PROXY`, `HTTPS_PROXY`,
+    /// `ALL_PROXY` and `NO_PROXY` environment variables.
+    ///
+    /// Both uppercase and lowercase variable names are recognized, with lowercase taking
+    /// precedence. Proxies added with `proxy` before this are tried first.
+    pub fn proxy_from_env(mut self) -> Self {
+        self.proxies.extend(Proxy::from_env());
+        self
+    }
+
+    /// Connect to the Unix domain socket at `path` for every request, instead of to the host
+    /// of the request URL.
+    ///
+    /// The URL is still used for the request target and `Host` header, and `https` requests
+    /// are secured as usual. Proxies are not used. To pick a socket per request instead, use
+    /// an `http+unix` URL whose host is the percent-encoded socket path, as in
+    /// `http+unix://%2Fvar%2Frun%2Fdocker.sock/containers/json`.
+    pub fn unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.unix_socket = Some(path.into());
+        self
+    }
+
+    /// Limit how long resolving the host and establishing a TCP connection may take.
+    ///
+    /// Opening a tunnel through a proxy, with `CONNECT` or SOCKS5, gets the same limit again.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how long the TLS handshake of `https` connections may take.
+    pub fn tls_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.tls_handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how long to wait for the response to start once the request has been sent.
+    pub fn first_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.first_byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how long a request may take from start until the response body has been read.
+    ///
+    /// If the deadline passes before the response head is received, sending the request fails.
+    /// If it passes while the body is being read, reading the body fails instead, with an
+    /// `io::Error` of kind `TimedOut` whose source is the `TimeoutError`.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Create the client.
+    pub fn build(self) -> H1Client {
+        H1Client {
+            inner: Arc::new(Inner {
+                pool: Arc::new(Pool::new(self.pool_config)),
+                resolver: self.resolver,
+                runtime: self.runtime,
+                tls: TlsConnector::new(self.tls_config),
+                proxies: self.proxies,
+                unix_socket: self.unix_socket,
+                connect_timeout: self.connect_timeout,
+                tls_handshake_timeout: self.tls_handshake_timeout,
+                first_byte_timeout: self.first_byte_timeout,
+                request_timeout: self.request_timeout,
+            }),
+        }
+    }
+}
+
+impl HttpClient for H1Client {
+    fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let runtime = &*inner.runtime;
+            let start = Instant::now();
+            let mut res = timeout(
+                runtime,
+                inner.request_timeout,
+                TimeoutKind::Request,
+                inner.send(req),
+            )
+            .await
+            .error_kind(ErrorKind::Other)?;
+            if let Some(duration) = inner.request_timeout {
+                timeout::body_deadline(runtime, &mut res, duration, start);
+            }
+            Ok(res)
+        })
+    }
+}
+
+impl Inner {
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        // Insert host
+        let host = req
+            .url()
+            .host_str()
+            .ok_or_else(|| kind_error(ErrorKind::InvalidRequest, "missing hostname"))?
+            .to_string();
+
+        let scheme = req.url().scheme().to_string();
+        let socket = match scheme.as_str() {
+            "http" | "https" => self.unix_socket.clone(),
+            unix::SCHEME => {
+                // The host of the URL is the socket path, which makes for a poor `Host` header.
+                if req.header(HOST).is_none() {
+                    req.insert_header(HOST, "localhost");
+                }
+                Some(unix::socket_path(req.url()).error_kind(ErrorKind::InvalidRequest)?)
+            }
+            _ => {
+                return Err(kind_error(
+                    ErrorKind::InvalidRequest,
+                    format!("invalid url scheme '{}'", scheme),
+                ))
+            }
+        };
+
+        // http and https always have a known default port, and sockets need none.
+        let port = req.url().port_or_known_default().unwrap_or(0);
+        let key = PoolKey::new(&scheme, &host, port);
+
+        log::trace!("> Scheme: {}", scheme);
+
+        let proxy = match socket {
+            Some(_) => None,
+            None => Proxy::select(&self.proxies, &scheme, &host),
+        };
+        let conn = match self.pool.checkout(&key) {
+            Some(conn) => {
+                log::trace!("reusing idle connection to {:?}", key);
+                conn
+            }
+            None => {
+                // UNWRAP: the host was checked to be present above.
+                let url_host = req.url().host().unwrap().to_owned();
+                self.connect(url_host, port, &scheme, host, proxy, socket.as_deref())
+                    .await?
+            }
+        };
+
+        let peer_addr = conn.peer_addr.clone();
+        let local_addr = conn.local_addr.clone();
+        req.set_peer_addr(peer_addr.as_ref());
+        req.set_local_addr(local_addr.as_ref());
+        let close_requested = wants_close(req.header(CONNECTION));
+
+        let mut checkout = Checkout::new(conn, key, self.pool.clone());
+        let reusable = checkout.reusable();
+
+        match proxy {
+            // Plain requests are forwarded by the proxy, which needs the full URL to do so.
+            Some(proxy) if proxy.kind() == ProxyKind::Http && scheme == "http" => {
+                if let Some(authorization) = proxy.authorization() {
+                    req.insert_header(PROXY_AUTHORIZATION, authorization);
+                }
+                let (method, url) = (req.method(), req.url().clone());
+                let mut encoder = AbsoluteForm::new(method, &url, client::Encoder::new(req));
+                futures::io::copy(&mut encoder, &mut checkout)
+                    .await
+                    .io_error_kind(ErrorKind::Body)?;
+            }
+            _ => {
+                let mut encoder = client::Encoder::new(req);
+                futures::io::copy(&mut encoder, &mut checkout)
+                    .await
+                    .io_error_kind(ErrorKind::Body)?;
+            }
+        }
+        let runtime = &*self.runtime;
+        let mut res = timeout(
+            runtime,
+            self.first_byte_timeout,
+            TimeoutKind::FirstByte,
+            async {
+                client::decode(checkout)
+                    .await
+                    .io_error_kind(ErrorKind::Protocol)
+            },
+        )
+        .await?;
+        // The decoder only accepts HTTP/1.1 responses, but doesn't say so.
+        res.set_version(Some(Version::Http1_1));
+        res.set_peer_addr(peer_addr);
+        res.set_local_addr(local_addr);
+
+        if close_requested || wants_close(res.header(CONNECTION)) {
+            return Ok(res);
+        }
+
+        let body = res.take_body();
+        if body.len() == Some(0) {
+            // Nothing left to read, so the connection can go back right away.
+            reusable.store(true, Ordering::Release);
+            drop(body);
+        } else {
+            let len = body.len();
+            let mime = body.mime().clone();
+            let mut body = Body::from_reader(ReleaseOnEof::new(body, len, reusable), len);
+            body.set_mime(mime);
+            res.set_body(body);
+        }
+
+        Ok(res)
+    }
+
+    /// Open a new connection to `url_host` on `port`, or to the Unix domain socket at `socket`
+    /// if there is one.
+    async fn connect(
+        &self,
+        url_host: Host<String>,
+        port: u16,
+        scheme: &str,
+        host: String,
+        proxy: Option<&Proxy>,
+        socket: Option<&Path>,
+    ) -> Result<Conn, Error> {
+        let runtime = &*self.runtime;
+        let (stream, peer_addr, local_addr) = match socket {
+            Some(path) => {
+                let conn = timeout(runtime, self.connect_timeout, TimeoutKind::Connect, async {
+                    runtime
+                        .connect_unix(path)
+                        .await
+                        .error_kind(ErrorKind::Connect)
+                })
+                .await?;
+                (conn.stream, Some(path.display().to_string()), None)
+            }
+            None => {
+                let conn = self
+                    .connect_tcp(url_host, port, scheme, &host, proxy)
+                    .await?;
+                let peer_addr = conn.peer_addr.map(|addr| addr.to_string());
+                let local_addr = conn.local_addr.map(|addr| addr.to_string());
+                (conn.stream, peer_addr, local_addr)
+            }
+        };
+
+        let stream = match scheme {
+            "https" => timeout(
+                runtime,
+                self.tls_handshake_timeout,
+                TimeoutKind::TlsHandshake,
+                self.tls.connect(&host, stream),
+            )
+            .await
+            .error_kind(ErrorKind::Tls)?,
+            _ => stream,
+        };
+
+        Ok(Conn {
+            stream,
+            peer_addr,
+            local_addr,
+        })
+    }
+
+    /// Open a TCP connection to `url_host` on `port`, through `proxy` if there is one.
+    async fn connect_tcp(
+        &self,
+        url_host: Host<String>,
+        port: u16,
+        scheme: &str,
+        host: &str,
+        proxy: Option<&Proxy>,
+    ) -> Result<Connection, Error> {
+        let proxy = match proxy {
+            Some(proxy) => proxy,
+            None => return self.dial(url_host, port).await,
+        };
+        if let ProxyKind::Socks5 { remote_dns } = proxy.kind() {
+            return self.connect_socks(url_host, port, proxy, remote_dns).await;
+        }
+
+        let mut conn = self.dial(proxy.host(), proxy.port()).await?;
+        if scheme == "https" {
+            let authority = format!("{}:{}", host, port);
+            let runtime = &*self.runtime;
+            timeout(runtime, self.connect_timeout, TimeoutKind::Connect, async {
+                proxy::tunnel(&mut conn.stream, &authority, proxy.authorization())
+                    .await
+                    .error_kind(ErrorKind::Connect)
+            })
+            .await?;
+        }
+        Ok(conn)
+    }
+
+    /// Open a TCP connection to `url_host` on `port` through the SOCKS5 proxy `proxy`.
+    ///
+    /// Unless the proxy resolves the host, its addresses are tried in turn, each over a new
+    /// connection to the proxy, as proxies close the connection once they've failed to connect.
+    async fn connect_socks(
+        &self,
+        url_host: Host<String>,
+        port: u16,
+        proxy: &Proxy,
+        remote_dns: bool,
+    ) -> Result<Connection, Error> {
+        let runtime = &*self.runtime;
+        let targets = match url_host {
+            Host::Domain(domain) if remote_dns => vec![socks::Target::Domain(domain, port)],
+            Host::Domain(domain) => {
+                timeout(runtime, self.connect_timeout, TimeoutKind::Connect, async {
+                    let lookup = self.resolver.resolve(&domain).await;
+                    let lookup = lookup.error_kind(ErrorKind::Dns)?;
+                    let ips = lookup.ips().iter();
+                    Ok(ips.map(|ip| socks::Target::Ip(*ip, port)).collect())
+                })
+                .await?
+            }
+            Host::Ipv4(ip) => vec![socks::Target::Ip(ip.into(), port)],
+            Host::Ipv6(ip) => vec![socks::Target::Ip(ip.into(), port)],
+        };
+
+        let mut last_err = kind_error(ErrorKind::Dns, "missing valid address");
+        for target in targets {
+            let mut conn = self.dial(proxy.host(), proxy.port()).await?;
+            let handshake = timeout(runtime, self.connect_timeout, TimeoutKind::Connect, async {
+                socks::connect(&mut conn.stream, &target, proxy.credentials())
+                    .await
+                    .error_kind(ErrorKind::Connect)
+            });
+            match handshake.await {
+                Ok(()) => return Ok(conn),
+                Err(err) => {
+                    log::trace!("SOCKS5 proxy failed to connect to {:?}: {}", target, err);
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Resolve `url_host` and open a TCP connection to it on `port`.
+    async fn dial(&self, url_host: Host<String>, port: u16) -> Result<Connection, Error> {
+        let runtime = &*self.runtime;
+        timeout(runtime, self.connect_timeout, TimeoutKind::Connect, async {
+            let addrs: Vec<SocketAddr> = match url_host {
+                Host::Domain(domain) => self
+                    .resolver
+                    .resolve(&domain)
+                    .await
+                    .error_kind(ErrorKind::Dns)?
+                    .ips()
+                    .iter()
+                    .map(|ip| SocketAddr::new(*ip, port))
+                    .collect(),
+                Host::Ipv4(ip) => vec![SocketAddr::new(ip.into(), port)],
+                Host::Ipv6(ip) => vec![SocketAddr::new(ip.into(), port)],
+            };
+            if addrs.is_empty() {
+                return Err(kind_error(ErrorKind::Dns, "missing valid address"));
+            }
+            tcp::connect(runtime, addrs)
+                .await
+                .error_kind(ErrorKind::Connect)
+        })
+        .await
+    }
+}
+
+/// Whether a `Connection` header asks for the connection to be closed.
+fn wants_close(header: Option<&http_types::headers::HeaderValues>) -> bool {
+    header.is_some_and(|values| {
+        values
+            .iter()
+            .flat_map(|value| value.as_str().split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("close"))
+    })
+}
+
+/// Decode the percent-encoded bytes in `input`.
+pub(crate) fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| {
+            std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        });
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::prelude::*;
+    use async_std::task;
+    use http_types::url::Url;
+    use http_types::{Result, StatusCode};
+    use std::time::Duration;
+
+    fn build_test_request(url: Url) -> Request {
+        let mut req = Request::new(http_types::Method::Post, url);
+        req.set_body("hello");
+        req.append_header("test", "value");
+        req
+    }
+
+    #[async_std::test]
+    async fn basic_functionality() -> Result<()> {
+        let port = portpicker::pick_unused_port().unwrap();
+        let mut app = tide::new();
+        app.at("/").all(|mut r: tide::Request<()>| async move {
+            let mut response = tide::Response::new(http_types::StatusCode::Ok);
+            response.set_body(r.body_bytes().await.unwrap());
+            Ok(response)
+        });
+
+        let server = task::spawn(async move {
+            app.listen(("localhost", port)).await?;
+            Result::Ok(())
+        });
+
+        let client = task::spawn(async move {
+            task::sleep(Duration::from_millis(100)).await;
+            let request =
+                build_test_request(Url::parse(&format!("http://localhost:{}/", port)).unwrap());
+            let mut response: Response = H1Client::new().send(request).await?;
+            assert_eq!(response.body_string().await.unwrap(), "hello");
+            Ok(())
+        });
+
+        server.race(client).await?;
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reuses_idle_connections() -> Result<()> {
+        let port = portpicker::pick_unused_port().unwrap();
+        let mut app = tide::new();
+        app.at("/").get(|r: tide::Request<()>| async move {
+            Ok(r.peer_addr().unwrap_or_default().to_string())
+        });
+
+        let server = task::spawn(async move {
+            app.listen(("localhost", port)).await?;
+            Result::Ok(())
+        });
+
+        let client = task::spawn(async move {
+            task::sleep(Duration::from_millis(100)).await;
+            let url = Url::parse(&format!("http://localhost:{}/", port)).unwrap();
+            let send = |client: H1Client| {
+                let url = url.clone();
+                async move {
+                    let req = Request::new(http_types::Method::Get, url);
+                    client.send(req).await?.body_string().await
+                }
+            };
+
+            let client = H1Client::new();
+            let first = send(client.clone()).await?;
+            let second = send(client.clone()).await?;
+            assert_eq!(first, second);
+
+            let client = H1Client::with_pool_config(PoolConfig {
+                max_idle_per_host: 0,
+                ..PoolConfig::default()
+            });
+            let first = send(client.clone()).await?;
+            let second = send(client.clone()).await?;
+            assert_ne!(first, second);
+            Ok(())
+        });
+
+        server.race(client).await?;
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn uses_custom_resolver() -> Result<()> {
+        let port = portpicker::pick_unused_port().unwrap();
+        let mut app = tide::new();
+        app.at("/").get(|_| async { Ok("resolved") });
+
+        let server = task::spawn(async move {
+            app.listen(("127.0.0.1", port)).await?;
+            Result::Ok(())
+        });
+
+        let client = task::spawn(async move {
+            task::sleep(Duration::from_millis(100)).await;
+            let resolver = StaticResolver::new().add("example.invalid", vec!["127.0.0.1".parse()?]);
+            let url = Url::parse(&format!("http://example.invalid:{}/", port)).unwrap();
+            let req = Request::new(http_types::Method::Get, url);
+            let mut res = H1Client::with_resolver(resolver).send(req).await?;
+            assert_eq!(res.body_string().await?, "resolved");
+            Ok(())
+        });
+
+        server.race(client).await?;
+
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct Unresponsive;
+
+    impl Resolver for Unresponsive {
+        fn resolve(&self, _host: &str) -> BoxFuture<'static, std::io::Result<Lookup>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    #[async_std::test]
+    async fn reports_which_timeout_elapsed() -> Result<()> {
+        // Accepts connections but never says a word.
+        let listener = async_std::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+        let _server = task::spawn(async move {
+            let mut streams = Vec::new();
+            let mut incoming = listener.incoming();
+            while let Some(stream) = incoming.next().await {
+                streams.push(stream);
+            }
+        });
+
+        let short = Duration::from_millis(50);
+        let long = Duration::from_secs(5);
+        let cases = vec![
+            (
+                H1Client::builder()
+                    .resolver(Unresponsive)
+                    .connect_timeout(short),
+                "http://example.invalid/".to_string(),
+                TimeoutKind::Connect,
+            ),
+            (
+                H1Client::builder()
+                    .proxy(Proxy::https(&format!("http://127.0.0.1:{}", port))?)
+                    .connect_timeout(short),
+                "https://example.invalid/".to_string(),
+                TimeoutKind::Connect,
+            ),
+            (
+                H1Client::builder()
+                    .proxy(Proxy::all(&format!("socks5h://127.0.0.1:{}", port))?)
+                    .connect_timeout(short),
+                "http://example.invalid/".to_string(),
+                TimeoutKind::Connect,
+            ),
+            (
+                H1Client::builder()
+                    .resolver(StaticResolver::new().add("localhost", vec!["127.0.0.1".parse()?]))
+                    .tls_handshake_timeout(short),
+                format!("https://localhost:{}/", port),
+                TimeoutKind::TlsHandshake,
+            ),
+            (
+                H1Client::builder().first_byte_timeout(short),
+                format!("http://127.0.0.1:{}/", port),
+                TimeoutKind::FirstByte,
+            ),
+            (
+                H1Client::builder()
+                    .first_byte_timeout(long)
+                    .request_timeout(short),
+                format!("http://127.0.0.1:{}/", port),
+                TimeoutKind::Request,
+            ),
+        ];
+
+        for (builder, url, kind) in cases {
+            let req = Request::new(http_types::Method::Get, Url::parse(&url)?);
+            let err = builder.build().send(req).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::GatewayTimeout);
+            assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout);
+            let err = err.downcast_ref::<crate::error::SendError>().unwrap();
+            let timeout = err.get_ref().downcast_ref::<TimeoutError>().unwrap();
+            assert_eq!(timeout.kind(), kind);
+            assert_eq!(timeout.duration(), short);
+        }
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn request_timeout_covers_the_body() -> Result<()> {
+        // Sends the head and part of the body, then stalls.
+        let listener = async_std::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+        let _server = task::spawn(async move {
+            let mut streams = Vec::new();
+            let mut incoming = listener.incoming();
+            while let Some(Ok(mut stream)) = incoming.next().await {
+                let res = b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nhello";
+                let _ = stream.write_all(res).await;
+                streams.push(stream);
+            }
+        });
+
+        let timeout = Duration::from_millis(200);
+        let client = H1Client::builder().request_timeout(timeout).build();
+        let url = Url::parse(&format!("http://127.0.0.1:{}/", port))?;
+        let mut res = client
+            .send(Request::new(http_types::Method::Get, url))
+            .await?;
+        let err = res.body_string().await.unwrap_err();
+        let err = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        let source = err.get_ref().unwrap().downcast_ref::<TimeoutError>();
+        assert_eq!(source.map(TimeoutError::kind), Some(TimeoutKind::Request));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reports_error_kinds() -> Result<()> {
+        let port = portpicker::pick_unused_port().unwrap();
+        let client = H1Client::with_resolver(
+            StaticResolver::new().add("localhost", vec!["127.0.0.1".parse()?]),
+        );
+        let closed = format!("http://localhost:{}/", port);
+        let cases = vec![
+            ("ftp://localhost/", ErrorKind::InvalidRequest),
+            ("http://nowhere.test/", ErrorKind::Dns),
+            (closed.as_str(), ErrorKind::Connect),
+        ];
+
+        for (url, kind) in cases {
+            let req = Request::new(http_types::Method::Get, Url::parse(url)?);
+            let err = client.send(req).await.unwrap_err();
+            assert_eq!(ErrorKind::of(&err), kind, "{}: {}", url, err);
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn evicts_connections_closed_by_server() -> Result<()> {
+        let listener = async_std::net::TcpListener::bind(("localhost", 0)).await?;
+        let port = listener.local_addr()?.port();
+
+        // Answer a single request per connection, then hang up without saying so.
+        let server = task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(stream) = incoming.next().await {
+                let mut stream = stream?;
+                let mut head = Vec::new();
+                let mut byte = [0; 1];
+                while !head.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).await?;
+                    head.push(byte[0]);
+                }
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                    .await?;
+            }
+            Result::Ok(())
+        });
+
+        let client = task::spawn(async move {
+            let client = H1Client::new();
+            let url = Url::parse(&format!("http://localhost:{}/", port)).unwrap();
+            for _ in 0..2 {
+                let req = Request::new(http_types::Method::Get, url.clone());
+                let mut res = client.send(req).await?;
+                assert_eq!(res.body_string().await?, "ok");
+                task::sleep(Duration::from_millis(50)).await;
+            }
+            Ok(())
+        });
+
+        server.race(client).await?;
+
+        Ok(())
+    }
+}
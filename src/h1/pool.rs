@@ -0,0 +1,319 @@
+//! Keep-alive connection pool for `H1Client`.
+
+use super::runtime::Stream;
+
+use futures::future::{self, FutureExt};
+use futures::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Configuration for the idle connection pool of an `H1Client`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PoolConfig {
+    /// The maximum number of idle connections kept per `(scheme, host, port)`.
+    ///
+    /// Setting this to `0` disables connection reuse. Defaults to `32`.
+    pub max_idle_per_host: usize,
+    /// How long a connection may sit idle in the pool before it is discarded.
+    ///
+    /// `None` keeps idle connections until the server closes them. Defaults to 90 seconds.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: 32,
+            idle_timeout: Some(Duration::from_secs(90)),
+        }
+    }
+}
+
+/// An established connection, along with its socket addresses.
+pub(crate) struct Conn {
+    pub(crate) stream: Box<dyn Stream>,
+    pub(crate) peer_addr: Option<String>,
+    pub(crate) local_addr: Option<String>,
+}
+
+impl fmt::Debug for Conn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conn")
+            .field("peer_addr", &self.peer_addr)
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+/// The origin a connection is bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PoolKey {
+    scheme: String,
+    host: String,
+    port: u16,
+}
+
+impl PoolKey {
+    pub(crate) fn new(scheme: &str, host: &str, port: u16) -> Self {
+        Self {
+            scheme: scheme.to_owned(),
+            host: host.to_owned(),
+            port,
+        }
+    }
+}
+
+struct Idle {
+    conn: Conn,
+    since: Instant,
+}
+
+/// A set of idle keep-alive connections, shared between clones of an `H1Client`.
+pub(crate) struct Pool {
+    config: PoolConfig,
+    idle: Mutex<HashMap<PoolKey, Vec<Idle>>>,
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let idle = self.idle.lock().unwrap();
+        f.debug_struct("Pool")
+            .field("config", &self.config)
+            .field("idle", &idle.values().map(Vec::len).sum::<usize>())
+            .finish()
+    }
+}
+
+impl Pool {
+    pub(crate) fn new(config: PoolConfig) -> Self {
+        Self {
+            config,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take the most recently used idle connection for `key` that is still open.
+    pub(crate) fn checkout(&self, key: &PoolKey) -> Option<Conn> {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.get_mut(key)?;
+        let mut found = None;
+        while let Some(mut entry) = conns.pop() {
+            if self.is_expired(&entry) || !is_open(&mut entry.conn) {
+                log::trace!("evicting idle connection to {:?}", key);
+                continue;
+            }
+            found = Some(entry.conn);
+            break;
+        }
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        found
+    }
+
+    /// Return a connection to the pool so it can be reused.
+    fn put(&self, key: PoolKey, conn: Conn) {
+        if self.config.max_idle_per_host == 0 {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.entry(key).or_default();
+        conns.retain(|entry| !self.is_expired(entry));
+        if conns.len() >= self.config.max_idle_per_host {
+            conns.remove(0);
+        }
+        conns.push(Idle {
+            conn,
+            since: Instant::now(),
+        });
+    }
+
+    fn is_expired(&self, entry: &Idle) -> bool {
+        match self.config.idle_timeout {
+            Some(timeout) => entry.since.elapsed() >= timeout,
+            None => false,
+        }
+    }
+}
+
+/// Check whether the server has closed an idle connection.
+///
+/// An idle HTTP/1.1 connection has nothing to read, so anything other than a pending read
+/// (EOF, an error, or unsolicited bytes) means it can't be reused.
+fn is_open(conn: &mut Conn) -> bool {
+    let mut buf = [0; 1];
+    let stream = &mut conn.stream;
+    future::poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, &mut buf))
+        .now_or_never()
+        .is_none()
+}
+
+/// A connection checked out of the pool for the duration of one request.
+///
+/// When dropped, the connection goes back to the pool if the response was read to completion,
+/// and is closed otherwise.
+pub(crate) struct Checkout {
+    conn: Option<Conn>,
+    key: PoolKey,
+    pool: Arc<Pool>,
+    reusable: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for Checkout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkout")
+            .field("conn", &self.conn)
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl Checkout {
+    pub(crate) fn new(conn: Conn, key: PoolKey, pool: Arc<Pool>) -> Self {
+        Self {
+            conn: Some(conn),
+            key,
+            pool,
+            reusable: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// A handle that marks this connection as reusable once set.
+    pub(crate) fn reusable(&self) -> Arc<AtomicBool> {
+        self.reusable.clone()
+    }
+
+    fn stream(&mut self) -> Pin<&mut (dyn Stream + 'static)> {
+        // UNWRAP: the connection is only taken out on drop.
+        Pin::new(&mut *self.conn.as_mut().unwrap().stream)
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        if self.reusable.load(Ordering::Acquire) {
+            if let Some(conn) = self.conn.take() {
+                self.pool.put(self.key.clone(), conn);
+            }
+        }
+    }
+}
+
+impl AsyncRead for Checkout {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().stream().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Checkout {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().stream().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().stream().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().stream().poll_close(cx)
+    }
+}
+
+/// A response body reader that releases its connection back to the pool once it reaches EOF.
+///
+/// When the body length is known, the connection is released as soon as that many bytes have
+/// been read, since `Body` won't poll its reader for the final EOF.
+pub(crate) struct ReleaseOnEof<R> {
+    inner: Option<R>,
+    remaining: Option<usize>,
+    reusable: Arc<AtomicBool>,
+}
+
+impl<R> ReleaseOnEof<R> {
+    pub(crate) fn new(inner: R, len: Option<usize>, reusable: Arc<AtomicBool>) -> Self {
+        Self {
+            inner: Some(inner),
+            remaining: len,
+            reusable,
+        }
+    }
+
+    fn advance(&mut self, amt: usize) {
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(amt);
+            if *remaining == 0 {
+                self.release();
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        self.reusable.store(true, Ordering::Release);
+        // Dropping the reader drops the `Checkout` it wraps, which hands the connection back.
+        self.inner = None;
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for ReleaseOnEof<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let inner = match this.inner.as_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(Ok(0)),
+        };
+        let read = futures::ready!(Pin::new(inner).poll_read(cx, buf))?;
+        if read == 0 && !buf.is_empty() {
+            this.release();
+        } else {
+            this.advance(read);
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for ReleaseOnEof<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        let eof = match this.inner.as_mut() {
+            Some(inner) => futures::ready!(Pin::new(inner).poll_fill_buf(cx))?.is_empty(),
+            None => return Poll::Ready(Ok(&[])),
+        };
+        if eof {
+            this.release();
+            return Poll::Ready(Ok(&[]));
+        }
+        // The buffer was just filled, so this returns the same bytes without further I/O.
+        match this.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_fill_buf(cx),
+            None => Poll::Ready(Ok(&[])),
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        if let Some(inner) = this.inner.as_mut() {
+            Pin::new(inner).consume(amt);
+            this.advance(amt);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Host name resolution for `H1Client`.
+
+use futures::future::BoxFuture;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Resolves host names into IP addresses.
+///
+/// `H1Client` only consults the resolver for domain names; IP literals in URLs are connected to
+/// directly.
+pub trait Resolver: fmt::Debug + Send + Sync + 'static {
+    /// Look up the addresses of `host`.
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Lookup>>;
+}
+
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Lookup>> {
+        (**self).resolve(host)
+    }
+}
+
+/// The result of resolving a host name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    ips: Vec<IpAddr>,
+    ttl: Option<Duration>,
+}
+
+impl Lookup {
+    /// Create a new lookup result from a list of addresses.
+    pub fn new(ips: Vec<IpAddr>) -> Self {
+        Self { ips, ttl: None }
+    }
+
+    /// Set how long this result may be cached for.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// The resolved addresses, in order of preference.
+    pub fn ips(&self) -> &[IpAddr] {
+        &self.ips
+    }
+
+    /// How long this result may be cached for, if the resolver knows.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+}
+
+/// The system resolver.
+///
+/// Lookups go through `getaddrinfo` on a blocking thread pool, so they never block the executor.
+/// The thread pool is independent of the `Runtime` of the client.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultResolver {
+    _priv: (),
+}
+
+impl DefaultResolver {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl Resolver for DefaultResolver {
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Lookup>> {
+        let host = format!("{}:0", host);
+        Box::pin(blocking::unblock(move || {
+            let ips = host.to_socket_addrs()?.map(|addr| addr.ip()).collect();
+            Ok(Lookup::new(ips))
+        }))
+    }
+}
+
+/// A resolver with fixed answers for some hosts, like curl's `--resolve`.
+///
+/// Hosts without an entry are passed on to the fallback resolver if there is one, and fail to
+/// resolve otherwise.
+///
+/// # Examples
+///
+/// ```
+/// use http_client::h1::{DefaultResolver, H1Client, StaticResolver};
+///
+/// let resolver = StaticResolver::new()
+///     .add("api.example.com", vec!["127.0.0.1".parse().unwrap()])
+///     .fallback(DefaultResolver::new());
+/// let client = H1Client::with_resolver(resolver);
+/// ```
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Box<dyn Resolver>>,
+}
+
+impl StaticResolver {
+    /// Create a new instance without any entries.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `host` to `ips`.
+    pub fn add(mut self, host: impl Into<String>, ips: Vec<IpAddr>) -> Self {
+        self.hosts.insert(host.into().to_ascii_lowercase(), ips);
+        self
+    }
+
+    /// Resolve hosts without an entry using `resolver`.
+    pub fn fallback(mut self, resolver: impl Resolver) -> Self {
+        self.fallback = Some(Box::new(resolver));
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Lookup>> {
+        if let Some(ips) = self.hosts.get(&host.to_ascii_lowercase()) {
+            let lookup = Lookup::new(ips.clone());
+            return Box::pin(async move { Ok(lookup) });
+        }
+        match &self.fallback {
+            Some(fallback) => fallback.resolve(host),
+            None => {
+                let err = io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no static address for host '{}'", host),
+                );
+                Box::pin(async move { Err(err) })
+            }
+        }
+    }
+}
+
+/// An in-memory cache in front of another resolver.
+///
+/// Results are kept for as long as their TTL says, or for the default TTL if the inner resolver
+/// doesn't report one. Failed lookups are not cached.
+pub struct CachingResolver<R> {
+    inner: R,
+    default_ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, (Lookup, Instant)>>>,
+}
+
+impl<R: fmt::Debug> fmt::Debug for CachingResolver<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachingResolver")
+            .field("inner", &self.inner)
+            .field("default_ttl", &self.default_ttl)
+            .finish()
+    }
+}
+
+impl<R: Resolver> CachingResolver<R> {
+    /// Create a new cache in front of `inner`, keeping results without a TTL for `default_ttl`.
+    pub fn new(inner: R, default_ttl: Duration) -> Self {
+        Self {
+            inner,
+            default_ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<R: Resolver> Resolver for CachingResolver<R> {
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Lookup>> {
+        let key = host.to_ascii_lowercase();
+        {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.get(&key) {
+                Some((lookup, expires)) if *expires > Instant::now() => {
+                    let lookup = lookup.clone();
+                    return Box::pin(async move { Ok(lookup) });
+                }
+                Some(_) => {
+                    entries.remove(&key);
+                }
+                None => {}
+            }
+        }
+
+        let lookup = self.inner.resolve(host);
+        let entries = self.entries.clone();
+        let default_ttl = self.default_ttl;
+        Box::pin(async move {
+            let lookup = lookup.await?;
+            let ttl = lookup.ttl().unwrap_or(default_ttl);
+            let expires = Instant::now() + ttl;
+            entries
+                .lock()
+                .unwrap()
+                .insert(key, (lookup.clone(), expires));
+            Ok(lookup)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Counting {
+        calls: AtomicUsize,
+        ttl: Option<Duration>,
+    }
+
+    impl Resolver for Counting {
+        fn resolve(&self, _host: &str) -> BoxFuture<'static, io::Result<Lookup>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut lookup = Lookup::new(vec!["127.0.0.1".parse().unwrap()]);
+            if let Some(ttl) = self.ttl {
+                lookup = lookup.with_ttl(ttl);
+            }
+            Box::pin(async move { Ok(lookup) })
+        }
+    }
+
+    #[async_std::test]
+    async fn static_entries_and_fallback() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let resolver = StaticResolver::new().add("Example.com", vec![ip]);
+
+        let lookup = resolver.resolve("example.COM").await.unwrap();
+        assert_eq!(lookup.ips(), &[ip]);
+        assert!(resolver.resolve("other.com").await.is_err());
+
+        let resolver = resolver.fallback(Counting::default());
+        let lookup = resolver.resolve("other.com").await.unwrap();
+        assert_eq!(lookup.ips(), &["127.0.0.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[async_std::test]
+    async fn caches_until_ttl_expires() {
+        let inner = Arc::new(Counting::default());
+        let resolver = CachingResolver::new(inner.clone(), Duration::from_secs(60));
+        resolver.resolve("example.com").await.unwrap();
+        resolver.resolve("example.com").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 1);
+
+        // A TTL reported by the inner resolver takes precedence over the default.
+        let inner = Arc::new(Counting {
+            ttl: Some(Duration::from_secs(0)),
+            ..Counting::default()
+        });
+        let resolver = CachingResolver::new(inner.clone(), Duration::from_secs(60));
+        resolver.resolve("example.com").await.unwrap();
+        resolver.resolve("example.com").await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,440 @@
+//! The async runtime `H1Client` connects and keeps time with.
+
+use futures::future::BoxFuture;
+use futures::io::{AsyncRead, AsyncWrite};
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A byte stream an HTTP/1.1 exchange can be run over.
+pub trait Stream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+/// Opens connections and waits for timeouts on behalf of an `H1Client`.
+///
+/// The HTTP/1.1 codec itself is runtime-agnostic, so this is all that ties a client to an
+/// executor. `AsyncStdRuntime`, `TokioRuntime` and `SmolRuntime` are available behind the
+/// `h1_async_std`, `h1_tokio` and `h1_smol` features; the first of them that is enabled is used by
+/// default.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "h1_tokio")]
+/// # fn main() {
+/// use http_client::h1::{H1Client, TokioRuntime};
+///
+/// let client = H1Client::builder().runtime(TokioRuntime::new()).build();
+/// # }
+/// # #[cfg(not(feature = "h1_tokio"))]
+/// # fn main() {}
+/// ```
+pub trait Runtime: fmt::Debug + Send + Sync + 'static {
+    /// Open a TCP connection to `addr`.
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<Connection>>;
+
+    /// Connect to the Unix domain socket at `path`.
+    ///
+    /// Fails with `io::ErrorKind::Unsupported` unless implemented.
+    fn connect_unix(&self, path: &Path) -> BoxFuture<'static, io::Result<Connection>> {
+        let err = io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "cannot connect to {}: Unix domain sockets are not supported by this runtime",
+                path.display()
+            ),
+        );
+        Box::pin(async move { Err(err) })
+    }
+
+    /// Wait until `duration` has passed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+impl<R: Runtime + ?Sized> Runtime for Arc<R> {
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<Connection>> {
+        (**self).connect_tcp(addr)
+    }
+
+    fn connect_unix(&self, path: &Path) -> BoxFuture<'static, io::Result<Connection>> {
+        (**self).connect_unix(path)
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        (**self).sleep(duration)
+    }
+}
+
+/// A connection opened by a `Runtime`, along with its socket addresses.
+pub struct Connection {
+    pub(crate) stream: Box<dyn Stream>,
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) local_addr: Option<SocketAddr>,
+}
+
+impl Connection {
+    /// Create a connection over `stream`, between `local_addr` and `peer_addr` if they are known.
+    pub fn new(
+        stream: impl Stream,
+        peer_addr: Option<SocketAddr>,
+        local_addr: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            stream: Box::new(stream),
+            peer_addr,
+            local_addr,
+        }
+    }
+
+    /// The address of the other end of the connection, if known.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// The address of this end of the connection, if known.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("peer_addr", &self.peer_addr)
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+/// The runtime an `H1Client` is built with unless another one is given.
+pub(crate) fn default_runtime() -> Box<dyn Runtime> {
+    #[cfg(feature = "h1_async_std")]
+    return Box::new(AsyncStdRuntime::new());
+    #[cfg(all(feature = "h1_tokio", not(feature = "h1_async_std")))]
+    return Box::new(TokioRuntime::new());
+    #[cfg(all(
+        feature = "h1_smol",
+        not(any(feature = "h1_async_std", feature = "h1_tokio"))
+    ))]
+    return Box::new(SmolRuntime::new());
+    #[cfg(not(any(feature = "h1_async_std", feature = "h1_tokio", feature = "h1_smol")))]
+    compile_error!("H1Client needs a runtime: enable `h1_async_std`, `h1_tokio` or `h1_smol`")
+}
+
+/// The async-std runtime.
+#[cfg(feature = "h1_async_std")]
+#[cfg_attr(feature = "docs", doc(cfg(h1_async_std)))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdRuntime {
+    _priv: (),
+}
+
+#[cfg(feature = "h1_async_std")]
+impl AsyncStdRuntime {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+#[cfg(feature = "h1_async_std")]
+impl Runtime for AsyncStdRuntime {
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<Connection>> {
+        Box::pin(async move {
+            let stream = async_std::net::TcpStream::connect(addr).await?;
+            let peer_addr = stream.peer_addr().ok();
+            let local_addr = stream.local_addr().ok();
+            Ok(Connection::new(stream, peer_addr, local_addr))
+        })
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(&self, path: &Path) -> BoxFuture<'static, io::Result<Connection>> {
+        let path = path.to_owned();
+        Box::pin(async move {
+            let stream = async_std::os::unix::net::UnixStream::connect(path).await?;
+            Ok(Connection::new(stream, None, None))
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// The tokio runtime.
+///
+/// Connections and timers are registered with the runtime of the task that sends the request,
+/// unless a handle to another one is given with `with_handle`, in which case requests can be sent
+/// from any executor.
+#[cfg(feature = "h1_tokio")]
+#[cfg_attr(feature = "docs", doc(cfg(h1_tokio)))]
+#[derive(Debug, Clone, Default)]
+pub struct TokioRuntime {
+    handle: Option<tokio1::runtime::Handle>,
+}
+
+#[cfg(feature = "h1_tokio")]
+impl TokioRuntime {
+    /// Create a new instance using the runtime requests are sent from.
+    ///
+    /// Sending a request outside of a tokio runtime panics.
+    pub fn new() -> Self {
+        Self { handle: None }
+    }
+
+    /// Create a new instance using the runtime behind `handle`.
+    pub fn with_handle(handle: tokio1::runtime::Handle) -> Self {
+        Self {
+            handle: Some(handle),
+        }
+    }
+
+    /// Run `fut` on the runtime behind the handle, if there is one, or in place otherwise.
+    fn run<T, F>(&self, fut: F) -> BoxFuture<'static, T>
+    where
+        T: Send + 'static,
+        F: std::future::Future<Output = T> + Send + 'static,
+    {
+        match &self.handle {
+            Some(handle) => {
+                let task = handle.spawn(fut);
+                // The task only fails if it panicked or the runtime shut down.
+                Box::pin(async move { task.await.expect("tokio task failed") })
+            }
+            None => Box::pin(fut),
+        }
+    }
+}
+
+#[cfg(feature = "h1_tokio")]
+impl Runtime for TokioRuntime {
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<Connection>> {
+        self.run(async move {
+            let stream = tokio1::net::TcpStream::connect(addr).await?;
+            let peer_addr = stream.peer_addr().ok();
+            let local_addr = stream.local_addr().ok();
+            Ok(Connection::new(
+                tokio_compat::Compat(stream),
+                peer_addr,
+                local_addr,
+            ))
+        })
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(&self, path: &Path) -> BoxFuture<'static, io::Result<Connection>> {
+        let path = path.to_owned();
+        self.run(async move {
+            let stream = tokio1::net::UnixStream::connect(path).await?;
+            Ok(Connection::new(tokio_compat::Compat(stream), None, None))
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.run(async move { tokio1::time::sleep(duration).await })
+    }
+}
+
+/// Adapting tokio's I/O traits to the ones of the `futures` crate.
+#[cfg(feature = "h1_tokio")]
+mod tokio_compat {
+    use futures::io::{AsyncRead, AsyncWrite};
+    use tokio1::io::ReadBuf;
+
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// A tokio stream, readable and writable through the `futures` traits.
+    #[derive(Debug)]
+    pub(super) struct Compat<T>(pub(super) T);
+
+    impl<T: tokio1::io::AsyncRead + Unpin> AsyncRead for Compat<T> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut buf = ReadBuf::new(buf);
+            match Pin::new(&mut self.0).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    impl<T: tokio1::io::AsyncWrite + Unpin> AsyncWrite for Compat<T> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+}
+
+/// The smol runtime, or rather the `async-io` reactor it is built on.
+///
+/// `async-io` drives its reactor on a thread of its own when no executor does, so this works
+/// from any executor, if less efficiently than one that is native to it.
+#[cfg(feature = "h1_smol")]
+#[cfg_attr(feature = "docs", doc(cfg(h1_smol)))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolRuntime {
+    _priv: (),
+}
+
+#[cfg(feature = "h1_smol")]
+impl SmolRuntime {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+#[cfg(feature = "h1_smol")]
+impl Runtime for SmolRuntime {
+    fn connect_tcp(&self, addr: SocketAddr) -> BoxFuture<'static, io::Result<Connection>> {
+        Box::pin(async move {
+            let stream = async_io::Async::<std::net::TcpStream>::connect(addr).await?;
+            let peer_addr = stream.get_ref().peer_addr().ok();
+            let local_addr = stream.get_ref().local_addr().ok();
+            Ok(Connection::new(stream, peer_addr, local_addr))
+        })
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(&self, path: &Path) -> BoxFuture<'static, io::Result<Connection>> {
+        let path = path.to_owned();
+        Box::pin(async move {
+            let stream = async_io::Async::<std::os::unix::net::UnixStream>::connect(path).await?;
+            Ok(Connection::new(stream, None, None))
+        })
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            async_io::Timer::after(duration).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::h1::{H1Client, H1ClientBuilder};
+    use crate::HttpClient;
+    use async_std::net::TcpListener;
+    use async_std::prelude::*;
+    use async_std::task;
+    use http_types::{Method, Request, Url};
+
+    /// Answer every request with its path, except for `/silent`, on an async-std task.
+    async fn serve() -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(mut stream)) = incoming.next().await {
+                task::spawn(async move {
+                    loop {
+                        let mut head = Vec::new();
+                        let mut byte = [0; 1];
+                        while !head.ends_with(b"\r\n\r\n") {
+                            if stream.read_exact(&mut byte).await.is_err() {
+                                return;
+                            }
+                            head.push(byte[0]);
+                        }
+                        let head = String::from_utf8_lossy(&head).into_owned();
+                        let path = head.split(' ').nth(1).unwrap_or_default().to_owned();
+                        if path == "/silent" {
+                            futures::future::pending::<()>().await;
+                        }
+                        let res = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+                            path.len(),
+                            path
+                        );
+                        let _ = stream.write_all(res.as_bytes()).await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    /// Check that a client built by `builder` sends requests and keeps time.
+    async fn exercise(builder: H1ClientBuilder, port: u16) -> http_types::Result<()> {
+        let client = builder
+            .first_byte_timeout(Duration::from_millis(50))
+            .build();
+        for path in &["/first", "/second"] {
+            let url = Url::parse(&format!("http://localhost:{}{}", port, path))?;
+            let mut res = client.send(Request::new(Method::Get, url)).await?;
+            assert_eq!(res.body_string().await?, *path);
+            assert!(res.peer_addr().is_some());
+        }
+
+        let url = Url::parse(&format!("http://127.0.0.1:{}/silent", port))?;
+        let err = client
+            .send(Request::new(Method::Get, url))
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout);
+        Ok(())
+    }
+
+    #[cfg(feature = "h1_async_std")]
+    #[async_std::test]
+    async fn async_std_runtime() -> http_types::Result<()> {
+        let port = serve().await;
+        exercise(H1Client::builder().runtime(AsyncStdRuntime::new()), port).await
+    }
+
+    #[cfg(feature = "h1_tokio")]
+    #[test]
+    fn tokio_runtime() -> http_types::Result<()> {
+        let port = task::block_on(serve());
+        let rt = tokio1::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let builder = H1Client::builder().runtime(TokioRuntime::new());
+        rt.block_on(exercise(builder, port))?;
+
+        // With a handle, requests can be sent from outside the runtime.
+        let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+        let handle = rt.handle().clone();
+        let driver = std::thread::spawn(move || rt.block_on(stopped));
+        let builder = H1Client::builder().runtime(TokioRuntime::with_handle(handle));
+        let res = futures::executor::block_on(exercise(builder, port));
+        drop(stop);
+        let _ = driver.join();
+        res
+    }
+
+    #[cfg(feature = "h1_smol")]
+    #[test]
+    fn smol_runtime() -> http_types::Result<()> {
+        let port = task::block_on(serve());
+        let builder = H1Client::builder().runtime(SmolRuntime::new());
+        futures::executor::block_on(exercise(builder, port))
+    }
+}
@@ -0,0 +1,146 @@
+//! TCP connection establishment for `H1Client`.
+
+use super::runtime::{Connection, Runtime};
+
+use futures::future::{self, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// How long to wait on a connection attempt before racing the next address (RFC 8305 §5).
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connect to the first reachable address out of `addrs`.
+///
+/// Addresses are tried Happy Eyeballs style: families are interleaved, and a new attempt is
+/// started whenever the previous one fails or has been pending for `CONNECTION_ATTEMPT_DELAY`.
+/// The first attempt to succeed wins, and the remaining ones are dropped. If every attempt fails,
+/// the returned error lists each address along with the reason it failed.
+pub(crate) async fn connect(
+    runtime: &dyn Runtime,
+    addrs: Vec<SocketAddr>,
+) -> io::Result<Connection> {
+    let mut pending = interleave(addrs).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut errors = Vec::new();
+
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(addr) => attempts.push(attempt(runtime, addr)),
+                None => break,
+            }
+        }
+
+        let delay = runtime.sleep(CONNECTION_ATTEMPT_DELAY);
+        match future::select(attempts.next(), delay).await {
+            Either::Left((Some((_, Ok(stream))), _)) => return Ok(stream),
+            Either::Left((Some((addr, Err(err))), _)) => {
+                log::trace!("failed to connect to {}: {}", addr, err);
+                errors.push((addr, err));
+                if let Some(addr) = pending.next() {
+                    attempts.push(attempt(runtime, addr));
+                }
+            }
+            Either::Left((None, _)) => {}
+            Either::Right(_) => {
+                if let Some(addr) = pending.next() {
+                    attempts.push(attempt(runtime, addr));
+                }
+            }
+        }
+    }
+
+    Err(aggregate(errors))
+}
+
+async fn attempt(runtime: &dyn Runtime, addr: SocketAddr) -> (SocketAddr, io::Result<Connection>) {
+    log::trace!("connecting to {}", addr);
+    (addr, runtime.connect_tcp(addr).await)
+}
+
+/// Order addresses so that address families alternate, starting with the family of the first
+/// address, while keeping the resolver's order within each family.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_v6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let (first, second) = if prefer_v6 { (v6, v4) } else { (v4, v6) };
+
+    let mut ordered = Vec::with_capacity(first.len() + second.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+fn aggregate(errors: Vec<(SocketAddr, io::Error)>) -> io::Error {
+    let kind = match errors.last() {
+        Some((_, err)) => err.kind(),
+        None => return io::Error::new(io::ErrorKind::InvalidInput, "missing valid address"),
+    };
+    let attempts = errors
+        .iter()
+        .map(|(addr, err)| format!("{} ({})", addr, err))
+        .collect::<Vec<_>>()
+        .join(", ");
+    io::Error::new(
+        kind,
+        format!("failed to connect to any address: {}", attempts),
+    )
+}
+
+#[cfg(all(test, feature = "h1_async_std"))]
+mod tests {
+    use super::*;
+    use crate::h1::AsyncStdRuntime;
+    use async_std::net::TcpListener;
+
+    async fn closed_port() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[test]
+    fn interleaves_address_families() {
+        let addrs: Vec<SocketAddr> = vec![
+            "[::1]:80".parse().unwrap(),
+            "[::2]:80".parse().unwrap(),
+            "[::3]:80".parse().unwrap(),
+            "127.0.0.1:80".parse().unwrap(),
+        ];
+        let ordered: Vec<String> = interleave(addrs).iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            ordered,
+            ["[::1]:80", "127.0.0.1:80", "[::2]:80", "[::3]:80"]
+        );
+    }
+
+    #[async_std::test]
+    async fn falls_back_to_next_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+
+        let addrs = vec![closed_port().await, open];
+        let conn = connect(&AsyncStdRuntime::new(), addrs).await.unwrap();
+        assert_eq!(conn.peer_addr(), Some(open));
+    }
+
+    #[async_std::test]
+    async fn reports_every_failed_address() {
+        let addrs = vec![closed_port().await, closed_port().await];
+
+        let err = connect(&AsyncStdRuntime::new(), addrs.clone())
+            .await
+            .unwrap_err();
+        for addr in addrs {
+            assert!(err.to_string().contains(&addr.to_string()), "{}", err);
+        }
+    }
+}
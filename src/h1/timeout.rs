@@ -0,0 +1,198 @@
+//! Timeouts for the phases of an `H1Client` request.
+
+use super::runtime::Runtime;
+use super::{Body, Error, Response};
+use crate::error::{ErrorKind, SendError};
+
+use futures::future::{self, BoxFuture, Either};
+use futures::io::{AsyncBufRead, AsyncRead};
+use http_types::StatusCode;
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The phase of a request that took too long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TimeoutKind {
+    /// Resolving the host and establishing a TCP connection, and opening a tunnel through a proxy.
+    Connect,
+    /// Completing the TLS handshake.
+    TlsHandshake,
+    /// Waiting for the response to start once the request was sent.
+    FirstByte,
+    /// The request as a whole, including reading the response body.
+    Request,
+}
+
+/// An `H1Client` request ran into one of its configured timeouts.
+///
+/// Returned wrapped in a `SendError` of kind `ErrorKind::Timeout`, with a status of
+/// `504 Gateway Timeout`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::error::SendError;
+/// use http_client::h1::{H1Client, TimeoutError, TimeoutKind};
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+/// use std::time::Duration;
+///
+/// let client = H1Client::builder()
+///     .connect_timeout(Duration::from_secs(1))
+///     .build();
+/// let req = Request::new(Method::Get, "http://example.com");
+/// match client.send(req).await {
+///     Err(err) => match err
+///         .downcast_ref::<SendError>()
+///         .and_then(|err| err.get_ref().downcast_ref::<TimeoutError>())
+///     {
+///         Some(timeout) if timeout.kind() == TimeoutKind::Connect => println!("unreachable"),
+///         _ => return Err(err),
+///     },
+///     Ok(_) => println!("connected"),
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError {
+    kind: TimeoutKind,
+    duration: Duration,
+}
+
+impl TimeoutError {
+    /// The phase of the request that timed out.
+    pub fn kind(&self) -> TimeoutKind {
+        self.kind
+    }
+
+    /// The configured timeout that elapsed.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self.kind {
+            TimeoutKind::Connect => "connect",
+            TimeoutKind::TlsHandshake => "TLS handshake",
+            TimeoutKind::FirstByte => "waiting for the response",
+            TimeoutKind::Request => "request",
+        };
+        write!(f, "{} timed out after {:?}", phase, self.duration)
+    }
+}
+
+impl StdError for TimeoutError {}
+
+/// Run `fut`, failing with a `TimeoutError` of `kind` if it doesn't finish within `duration`.
+pub(crate) async fn timeout<T, F>(
+    runtime: &dyn Runtime,
+    duration: Option<Duration>,
+    kind: TimeoutKind,
+    fut: F,
+) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    let duration = match duration {
+        Some(duration) => duration,
+        None => return fut.await,
+    };
+    futures::pin_mut!(fut);
+    match future::select(fut, runtime.sleep(duration)).await {
+        Either::Left((res, _)) => res,
+        Either::Right(_) => Err(Error::new(
+            StatusCode::GatewayTimeout,
+            SendError::new(ErrorKind::Timeout, TimeoutError { kind, duration }),
+        )),
+    }
+}
+
+/// Make reads of the body of `res` fail with a `TimeoutError` of kind `Request` once `duration`
+/// has passed since `start`.
+///
+/// The error is an `io::Error` of kind `TimedOut`, since that's what body reads return.
+pub(crate) fn body_deadline(
+    runtime: &dyn Runtime,
+    res: &mut Response,
+    duration: Duration,
+    start: Instant,
+) {
+    let body = res.take_body();
+    if body.len() == Some(0) {
+        res.set_body(body);
+        return;
+    }
+    let len = body.len();
+    let mime = body.mime().clone();
+    let deadline = Deadline {
+        inner: body,
+        sleep: Mutex::new(Some(
+            runtime.sleep(duration.saturating_sub(start.elapsed())),
+        )),
+        duration,
+    };
+    let mut body = Body::from_reader(deadline, len);
+    body.set_mime(mime);
+    res.set_body(body);
+}
+
+/// A body that fails once a sleep has finished.
+struct Deadline {
+    inner: Body,
+    /// The sleep, until it has finished. Behind a mutex only to make the body `Sync`.
+    sleep: Mutex<Option<BoxFuture<'static, ()>>>,
+    duration: Duration,
+}
+
+impl Deadline {
+    fn poll_elapsed(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let sleep = self.sleep.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if let Some(fut) = sleep {
+            if fut.as_mut().poll(cx).is_pending() {
+                return Ok(());
+            }
+            *sleep = None;
+        }
+        let err = TimeoutError {
+            kind: TimeoutKind::Request,
+            duration: self.duration,
+        };
+        Err(io::Error::new(io::ErrorKind::TimedOut, err))
+    }
+}
+
+impl AsyncRead for Deadline {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.poll_elapsed(cx)?;
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncBufRead for Deadline {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        this.poll_elapsed(cx)?;
+        Pin::new(&mut this.inner).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
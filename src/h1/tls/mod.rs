@@ -0,0 +1,426 @@
+//! TLS configuration for `H1Client`.
+//!
+//! Connections are secured with the platform's native TLS library when the `h1_client_native_tls`
+//! feature is enabled, and with rustls when `h1_client_rustls` is. When both are enabled, rustls
+//! is used. Both share the same configuration, with two exceptions under rustls: PKCS #12
+//! identities aren't supported, and hosts must be domain names rather than IP addresses.
+
+use super::runtime::Stream;
+use super::Error;
+
+use http_types::StatusCode;
+
+use std::fmt;
+use std::sync::OnceLock;
+
+#[cfg(all(feature = "h1_client_native_tls", not(feature = "h1_client_rustls")))]
+mod native;
+#[cfg(all(feature = "h1_client_native_tls", not(feature = "h1_client_rustls")))]
+use native as backend;
+
+#[cfg(feature = "h1_client_rustls")]
+mod rustls;
+#[cfg(feature = "h1_client_rustls")]
+use self::rustls as backend;
+
+/// A root certificate to trust when verifying servers.
+#[derive(Clone)]
+pub struct Certificate {
+    repr: CertificateRepr,
+}
+
+#[derive(Clone)]
+enum CertificateRepr {
+    Pem(Vec<u8>),
+    Der(Vec<u8>),
+}
+
+impl Certificate {
+    /// Create a certificate from PEM encoded bytes.
+    pub fn from_pem(pem: impl Into<Vec<u8>>) -> Self {
+        Self {
+            repr: CertificateRepr::Pem(pem.into()),
+        }
+    }
+
+    /// Create a certificate from DER encoded bytes.
+    pub fn from_der(der: impl Into<Vec<u8>>) -> Self {
+        Self {
+            repr: CertificateRepr::Der(der.into()),
+        }
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match self.repr {
+            CertificateRepr::Pem(_) => "PEM",
+            CertificateRepr::Der(_) => "DER",
+        };
+        f.debug_struct("Certificate")
+            .field("format", &format)
+            .finish()
+    }
+}
+
+/// A client certificate and private key to present to servers that ask for one.
+#[derive(Clone)]
+pub struct Identity {
+    repr: IdentityRepr,
+}
+
+#[derive(Clone)]
+enum IdentityRepr {
+    // rustls rejects PKCS #12 identities without looking at them.
+    #[cfg_attr(feature = "h1_client_rustls", allow(dead_code))]
+    Pkcs12 {
+        der: Vec<u8>,
+        password: String,
+    },
+    Pem {
+        cert: Vec<u8>,
+        key: Vec<u8>,
+    },
+}
+
+impl Identity {
+    /// Create an identity from a DER encoded PKCS #12 archive and its password.
+    pub fn from_pkcs12(der: impl Into<Vec<u8>>, password: impl Into<String>) -> Self {
+        Self {
+            repr: IdentityRepr::Pkcs12 {
+                der: der.into(),
+                password: password.into(),
+            },
+        }
+    }
+
+    /// Create an identity from a PEM encoded certificate chain and PEM encoded PKCS #8 key.
+    pub fn from_pem(cert: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            repr: IdentityRepr::Pem {
+                cert: cert.into(),
+                key: key.into(),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = match self.repr {
+            IdentityRepr::Pkcs12 { .. } => "PKCS #12",
+            IdentityRepr::Pem { .. } => "PEM",
+        };
+        f.debug_struct("Identity").field("format", &format).finish()
+    }
+}
+
+/// A version of the TLS protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.0
+    Tls10,
+    /// TLS 1.1
+    Tls11,
+    /// TLS 1.2
+    Tls12,
+    /// TLS 1.3
+    Tls13,
+}
+
+/// TLS settings for the `https` connections of an `H1Client`.
+///
+/// Connections are secured with native TLS under the `h1_client_native_tls` feature, and with
+/// rustls under `h1_client_rustls`. If both features are enabled, rustls is used.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// use http_client::h1::{Certificate, H1Client, Identity, TlsConfig};
+///
+/// let tls = TlsConfig::new()
+///     .add_root_certificate(Certificate::from_pem(std::fs::read("ca.pem")?))
+///     .identity(Identity::from_pkcs12(std::fs::read("client.p12")?, "password"));
+/// let client = H1Client::builder().tls_config(tls).build();
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    root_certificates: Vec<Certificate>,
+    identity: Option<Identity>,
+    min_protocol_version: Option<TlsVersion>,
+    server_name: Option<String>,
+    accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    /// Create a new configuration that trusts the default root certificates.
+    ///
+    /// These are the system's root certificates with native TLS, and the Mozilla root
+    /// certificates from `webpki-roots` with rustls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust `cert` in addition to the system's root certificates.
+    pub fn add_root_certificate(mut self, cert: Certificate) -> Self {
+        self.root_certificates.push(cert);
+        self
+    }
+
+    /// Present `identity` to servers that request a client certificate.
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Refuse to negotiate protocol versions older than `version`.
+    pub fn min_protocol_version(mut self, version: TlsVersion) -> Self {
+        self.min_protocol_version = Some(version);
+        self
+    }
+
+    /// Send `name` as the SNI server name, and verify the server's certificate against it,
+    /// instead of using the host of the request URL.
+    pub fn server_name(mut self, name: impl Into<String>) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Accept any server certificate, including expired, self-signed and mismatched ones.
+    ///
+    /// # Warning
+    ///
+    /// This makes connections vulnerable to man-in-the-middle attacks. Only use it to talk to
+    /// local test servers.
+    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+}
+
+/// The connector of an `H1Client`, built from its `TlsConfig` for the first handshake and reused
+/// for the rest.
+pub(crate) struct TlsConnector {
+    config: TlsConfig,
+    /// The connector, or the message of the error building it.
+    connector: OnceLock<Result<backend::Connector, String>>,
+}
+
+impl TlsConnector {
+    pub(crate) fn new(config: TlsConfig) -> Self {
+        Self {
+            config,
+            connector: OnceLock::new(),
+        }
+    }
+
+    /// Perform a TLS handshake with `host` over `stream`.
+    pub(crate) async fn connect(
+        &self,
+        host: &str,
+        stream: impl Stream,
+    ) -> Result<Box<dyn Stream>, Error> {
+        let connector = self
+            .connector
+            .get_or_init(|| backend::connector(&self.config).map_err(|err| err.to_string()));
+        let connector = connector
+            .as_ref()
+            .map_err(|msg| Error::from_str(StatusCode::InternalServerError, msg.clone()))?;
+        let host = self.config.server_name.as_deref().unwrap_or(host);
+        backend::connect(connector, host, stream).await
+    }
+}
+
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnector")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+fn invalid_config(err: impl fmt::Display) -> Error {
+    Error::from_str(
+        StatusCode::InternalServerError,
+        format!("invalid TLS configuration: {}", err),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::h1::{H1Client, StaticResolver};
+    use crate::HttpClient;
+    use async_std::io::{Read, Write};
+    use async_std::net::TcpListener;
+    use async_std::prelude::*;
+    use async_std::task;
+    use http_types::{Method, Request, Url};
+
+    const CA: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/ca.pem"
+    ));
+    const CA_DER: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/ca.der"
+    ));
+    const CLIENT: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/client.pem"
+    ));
+    const CLIENT_KEY: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/client.key"
+    ));
+    const SERVER: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/server.p12"
+    ));
+
+    const SERVER_CERT: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/server.pem"
+    ));
+    const SERVER_KEY: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/tls/server.key"
+    ));
+
+    /// Serve `ok` over TLS to every connection, one request each.
+    pub(crate) async fn serve() -> u16 {
+        let acceptor = async_native_tls::TlsAcceptor::new(SERVER, "password")
+            .await
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(stream)) = incoming.next().await {
+                let acceptor = acceptor.clone();
+                task::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        respond(stream).await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    /// Serve `ok` over TLS to every connection that presents a client certificate issued by
+    /// the test CA, one request each.
+    async fn serve_requiring_client_auth() -> u16 {
+        use ::rustls::internal::pemfile;
+        use ::rustls::{AllowAnyAuthenticatedClient, RootCertStore, ServerConfig};
+
+        let mut roots = RootCertStore::empty();
+        roots.add_pem_file(&mut &CA[..]).unwrap();
+        let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(roots));
+        let certs = pemfile::certs(&mut &SERVER_CERT[..]).unwrap();
+        let mut keys = pemfile::pkcs8_private_keys(&mut &SERVER_KEY[..]).unwrap();
+        config.set_single_cert(certs, keys.remove(0)).unwrap();
+        let acceptor = async_tls::TlsAcceptor::from(config);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(stream)) = incoming.next().await {
+                let accept = acceptor.accept(stream);
+                task::spawn(async move {
+                    if let Ok(stream) = accept.await {
+                        respond(stream).await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    /// Answer the request on `stream` with `ok`.
+    async fn respond(mut stream: impl Read + Write + Unpin) {
+        let mut head = Vec::new();
+        let mut byte = [0; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            if stream.read_exact(&mut byte).await.is_err() {
+                return;
+            }
+            head.push(byte[0]);
+        }
+        let res = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+        let _ = stream.write_all(res).await;
+    }
+
+    async fn get(tls: TlsConfig, url: String) -> http_types::Result<String> {
+        let resolver = StaticResolver::new()
+            .add("localhost", vec!["127.0.0.1".parse()?])
+            .add("elsewhere.invalid", vec!["127.0.0.1".parse()?]);
+        let client = H1Client::builder()
+            .resolver(resolver)
+            .tls_config(tls)
+            .build();
+        let req = Request::new(Method::Get, Url::parse(&url)?);
+        client.send(req).await?.body_string().await
+    }
+
+    #[async_std::test]
+    async fn trusts_added_root_certificates() {
+        let port = serve().await;
+        let url = format!("https://localhost:{}/", port);
+
+        assert!(get(TlsConfig::new(), url.clone()).await.is_err());
+
+        let tls = TlsConfig::new().add_root_certificate(Certificate::from_pem(CA));
+        assert_eq!(get(tls, url.clone()).await.unwrap(), "ok");
+
+        let tls = TlsConfig::new().add_root_certificate(Certificate::from_der(CA_DER));
+        assert_eq!(get(tls, url).await.unwrap(), "ok");
+    }
+
+    #[async_std::test]
+    async fn overrides_server_name() {
+        let port = serve().await;
+        // The server certificate is only valid for `localhost` and `127.0.0.1`.
+        let url = format!("https://elsewhere.invalid:{}/", port);
+        let tls = TlsConfig::new().add_root_certificate(Certificate::from_pem(CA));
+
+        assert!(get(tls.clone(), url.clone()).await.is_err());
+        let tls = tls.server_name("localhost");
+        assert_eq!(get(tls, url).await.unwrap(), "ok");
+    }
+
+    #[async_std::test]
+    async fn accepts_invalid_certs_when_asked_to() {
+        let port = serve().await;
+        let url = format!("https://localhost:{}/", port);
+
+        let tls = TlsConfig::new().danger_accept_invalid_certs(true);
+        assert_eq!(get(tls, url).await.unwrap(), "ok");
+    }
+
+    #[async_std::test]
+    async fn loads_pem_identities() {
+        let port = serve_requiring_client_auth().await;
+        let url = format!("https://localhost:{}/", port);
+
+        let tls = TlsConfig::new().add_root_certificate(Certificate::from_pem(CA));
+        assert!(get(tls.clone(), url.clone()).await.is_err());
+
+        let tls = tls.identity(Identity::from_pem(CLIENT, CLIENT_KEY));
+        assert_eq!(get(tls, url).await.unwrap(), "ok");
+    }
+
+    #[async_std::test]
+    async fn reports_invalid_configuration() {
+        let port = serve().await;
+        let url = format!("https://localhost:{}/", port);
+
+        let tls = TlsConfig::new().identity(Identity::from_pkcs12(&b"not a p12"[..], ""));
+        let err = get(tls, url).await.unwrap_err();
+        assert!(err.to_string().contains("invalid TLS configuration"));
+    }
+}
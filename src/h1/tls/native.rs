@@ -0,0 +1,52 @@
+//! TLS connections through the platform's native TLS library.
+
+use super::{invalid_config, CertificateRepr, IdentityRepr, TlsConfig, TlsVersion};
+use crate::h1::runtime::Stream;
+use crate::Error;
+
+use async_native_tls::{Certificate, Identity, Protocol, TlsConnector};
+
+pub(crate) type Connector = TlsConnector;
+
+/// Perform a TLS handshake with `host` over `stream`.
+pub(crate) async fn connect(
+    connector: &TlsConnector,
+    host: &str,
+    stream: impl Stream,
+) -> Result<Box<dyn Stream>, Error> {
+    Ok(Box::new(connector.connect(host, stream).await?))
+}
+
+/// Build a connector for `config`.
+///
+/// async-native-tls still builds the native connector from it on every handshake, but the
+/// certificates and identity are only parsed once.
+pub(crate) fn connector(config: &TlsConfig) -> Result<TlsConnector, Error> {
+    let mut connector = TlsConnector::new();
+    for cert in &config.root_certificates {
+        let cert = match &cert.repr {
+            CertificateRepr::Pem(pem) => Certificate::from_pem(pem),
+            CertificateRepr::Der(der) => Certificate::from_der(der),
+        };
+        connector = connector.add_root_certificate(cert.map_err(invalid_config)?);
+    }
+    if let Some(identity) = &config.identity {
+        let identity = match &identity.repr {
+            IdentityRepr::Pkcs12 { der, password } => Identity::from_pkcs12(der, password),
+            IdentityRepr::Pem { cert, key } => Identity::from_pkcs8(cert, key),
+        };
+        connector = connector.identity(identity.map_err(invalid_config)?);
+    }
+    if let Some(version) = config.min_protocol_version {
+        let version = match version {
+            TlsVersion::Tls10 => Protocol::Tlsv10,
+            TlsVersion::Tls11 => Protocol::Tlsv11,
+            TlsVersion::Tls12 => Protocol::Tlsv12,
+            TlsVersion::Tls13 => Protocol::Tlsv13,
+        };
+        connector = connector.min_protocol_version(Some(version));
+    }
+    Ok(connector
+        .danger_accept_invalid_certs(config.accept_invalid_certs)
+        .danger_accept_invalid_hostnames(config.accept_invalid_certs))
+}
@@ -0,0 +1,93 @@
+//! TLS connections through rustls.
+
+use super::{invalid_config, CertificateRepr, IdentityRepr, TlsConfig, TlsVersion};
+use crate::h1::runtime::Stream;
+use crate::Error;
+
+use async_tls::TlsConnector;
+use rustls::internal::pemfile;
+use rustls::{
+    Certificate, ClientConfig, ProtocolVersion, RootCertStore, ServerCertVerified,
+    ServerCertVerifier, TLSError,
+};
+
+use std::sync::Arc;
+
+pub(crate) type Connector = TlsConnector;
+
+/// Perform a TLS handshake with `host` over `stream`.
+pub(crate) async fn connect(
+    connector: &TlsConnector,
+    host: &str,
+    stream: impl Stream,
+) -> Result<Box<dyn Stream>, Error> {
+    Ok(Box::new(connector.connect(host, stream).await?))
+}
+
+/// Build a connector for `config`.
+pub(crate) fn connector(config: &TlsConfig) -> Result<TlsConnector, Error> {
+    Ok(TlsConnector::from(Arc::new(client_config(config)?)))
+}
+
+fn client_config(config: &TlsConfig) -> Result<ClientConfig, Error> {
+    let mut client = ClientConfig::new();
+    client
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    for cert in &config.root_certificates {
+        match &cert.repr {
+            CertificateRepr::Pem(pem) => match client.root_store.add_pem_file(&mut &pem[..]) {
+                Ok((added, _)) if added > 0 => {}
+                _ => return Err(invalid_config("no valid certificate in PEM data")),
+            },
+            CertificateRepr::Der(der) => client
+                .root_store
+                .add(&Certificate(der.clone()))
+                .map_err(invalid_config)?,
+        }
+    }
+    if let Some(identity) = &config.identity {
+        let (cert, key) = match &identity.repr {
+            IdentityRepr::Pem { cert, key } => (cert, key),
+            IdentityRepr::Pkcs12 { .. } => {
+                return Err(invalid_config(
+                    "PKCS #12 identities are not supported by rustls, use PEM instead",
+                ))
+            }
+        };
+        let certs = pemfile::certs(&mut &cert[..])
+            .map_err(|_| invalid_config("invalid client certificate"))?;
+        let key = pemfile::pkcs8_private_keys(&mut &key[..])
+            .ok()
+            .and_then(|keys| keys.into_iter().next())
+            .ok_or_else(|| invalid_config("invalid PKCS #8 client key"))?;
+        client
+            .set_single_client_cert(certs, key)
+            .map_err(invalid_config)?;
+    }
+    if let Some(TlsVersion::Tls13) = config.min_protocol_version {
+        // rustls doesn't support anything older than TLS 1.2 to begin with.
+        client.versions = vec![ProtocolVersion::TLSv1_3];
+    }
+    if config.accept_invalid_certs {
+        client
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+    }
+    Ok(client)
+}
+
+/// A certificate verifier that trusts every server.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: webpki::DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
@@ -0,0 +1,102 @@
+//! Unix domain socket transport for `H1Client`.
+
+use super::{percent_decode, Error};
+
+use http_types::url::Url;
+use http_types::StatusCode;
+
+use std::path::PathBuf;
+
+/// The URL scheme of plain HTTP requests sent over a Unix domain socket.
+///
+/// The host of such URLs is the percent-encoded path of the socket, as in
+/// `http+unix://%2Fvar%2Frun%2Fdocker.sock/containers/json`.
+pub(crate) const SCHEME: &str = "http+unix";
+
+/// The socket path encoded in the host of an `http+unix` URL.
+pub(crate) fn socket_path(url: &Url) -> Result<PathBuf, Error> {
+    match url.host_str() {
+        Some(host) if !host.is_empty() => Ok(PathBuf::from(percent_decode(host))),
+        _ => Err(Error::from_str(
+            StatusCode::BadRequest,
+            "missing socket path in http+unix URL",
+        )),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::h1::H1Client;
+    use crate::HttpClient;
+    use async_std::os::unix::net::UnixListener;
+    use async_std::prelude::*;
+    use async_std::task;
+    use http_types::{Method, Request};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answer every request on a fresh socket with its request head.
+    async fn serve() -> PathBuf {
+        static SOCKETS: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "http-client-{}-{}.sock",
+            std::process::id(),
+            SOCKETS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).await.unwrap();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(mut stream)) = incoming.next().await {
+                task::spawn(async move {
+                    loop {
+                        let mut head = Vec::new();
+                        let mut byte = [0; 1];
+                        while !head.ends_with(b"\r\n\r\n") {
+                            if stream.read_exact(&mut byte).await.is_err() {
+                                return;
+                            }
+                            head.push(byte[0]);
+                        }
+                        let res =
+                            format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", head.len());
+                        let _ = stream.write_all(res.as_bytes()).await;
+                        let _ = stream.write_all(&head).await;
+                    }
+                });
+            }
+        });
+        path
+    }
+
+    #[async_std::test]
+    async fn sends_http_unix_urls_over_the_socket() -> http_types::Result<()> {
+        let path = serve().await;
+        let host = path.display().to_string().replace('/', "%2F");
+        let url = Url::parse(&format!("http+unix://{}/containers/json?all=1", host))?;
+        assert_eq!(socket_path(&url)?, path);
+
+        let client = H1Client::new();
+        for _ in 0..2 {
+            let mut res = client.send(Request::new(Method::Get, url.clone())).await?;
+            let head = res.body_string().await?;
+            assert!(head.starts_with("GET /containers/json?all=1 HTTP/1.1\r\n"));
+            assert!(head.contains("host: localhost\r\n"), "{}", head);
+        }
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn overrides_the_transport_of_every_request() -> http_types::Result<()> {
+        let path = serve().await;
+        let client = H1Client::builder().unix_socket(&path).build();
+        let url = Url::parse("http://api.invalid/v1/status")?;
+        let mut res = client.send(Request::new(Method::Get, url)).await?;
+        let head = res.body_string().await?;
+        assert!(head.starts_with("GET /v1/status HTTP/1.1\r\n"));
+        assert!(head.contains("host: api.invalid\r\n"), "{}", head);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
 //! http-client implementation for isahc
 
 use super::{Body, Error, HttpClient, Request, Response};
+use crate::error::{with_kind, ErrorKind, ResultExt};
 
 use async_std::io::BufReader;
 use futures::future::BoxFuture;
-use isahc::http;
+use http_types::headers::HeaderValue;
+use isahc::config::Configurable;
+use isahc::{http, ResponseExt};
 use std::sync::Arc;
 
 /// Curl-based HTTP Client.
@@ -47,10 +50,13 @@ impl HttpClient for IsahcClient {
         Box::pin(async move {
             let mut builder = http::Request::builder()
                 .uri(req.url().as_str())
-                .method(http::Method::from_bytes(req.method().to_string().as_bytes()).unwrap());
+                .method(http::Method::from_bytes(req.method().to_string().as_bytes()).unwrap())
+                // Leave content encodings to `Decompress`, like the other backends do, rather
+                // than to whatever curl was built with.
+                .automatic_decompression(false);
 
-            for name in req.header_names() {
-                if let Some(value) = req.header(name) {
+            for (name, values) in req.iter() {
+                for value in values.iter() {
                     builder = builder.header(name.as_str(), value.as_str());
                 }
             }
@@ -62,14 +68,24 @@ impl HttpClient for IsahcClient {
                 None => isahc::Body::from_reader(body),
             };
 
-            let request = builder.body(body).unwrap();
-            let res = client.send_async(request).await.map_err(Error::from)?;
+            let request = builder.body(body).error_kind(ErrorKind::InvalidRequest)?;
+            let res = client.send_async(request).await.map_err(|err| {
+                let kind = error_kind(&err);
+                with_kind(err.into(), kind)
+            })?;
+            let peer_addr = res.remote_addr();
+            let local_addr = res.local_addr();
             let (parts, body) = res.into_parts();
             let len = body.len().map(|len| len as usize);
             let body = Body::from_reader(BufReader::new(body), len);
             let mut response = http_types::Response::new(parts.status.as_u16());
+            response.set_version(Some(parts.version.into()));
+            response.set_peer_addr(peer_addr);
+            response.set_local_addr(local_addr);
             for (name, value) in &parts.headers {
-                response.insert_header(name.as_str(), value.to_str().unwrap());
+                let value = HeaderValue::from_bytes(value.as_bytes().to_owned())
+                    .error_kind(ErrorKind::Protocol)?;
+                response.append_header(name.as_str(), value);
             }
             response.set_body(body);
             Ok(response)
@@ -77,6 +93,27 @@ impl HttpClient for IsahcClient {
     }
 }
 
+/// The kind of failure behind `err`.
+fn error_kind(err: &isahc::Error) -> ErrorKind {
+    use isahc::Error::*;
+    match err {
+        CouldntResolveHost | CouldntResolveProxy => ErrorKind::Dns,
+        ConnectFailed | NoResponse => ErrorKind::Connect,
+        BadClientCertificate(_)
+        | BadServerCertificate(_)
+        | SSLConnectFailed(_)
+        | SSLEngineError(_) => ErrorKind::Tls,
+        Timeout => ErrorKind::Timeout,
+        InvalidContentEncoding(_) | InvalidUtf8 | ResponseBodyError(_) => ErrorKind::Protocol,
+        RequestBodyError(_) => ErrorKind::Body,
+        InvalidHttpFormat(_) => ErrorKind::InvalidRequest,
+        Aborted => ErrorKind::Cancelled,
+        Io(_) | Curl(_) | InvalidCredentials | RangeRequestUnsupported | TooManyRedirects => {
+            ErrorKind::Other
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,4 +158,41 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn reports_error_kinds() -> Result<()> {
+        let port = portpicker::pick_unused_port().unwrap();
+        let url = Url::parse(&format!("http://127.0.0.1:{}/", port))?;
+        let err = IsahcClient::new()
+            .send(build_test_request(url))
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Connect, "{}", err);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rejects_non_ascii_header_values() -> Result<()> {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0; 1];
+            while !head.ends_with(b"\r\n\r\n") {
+                stream.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+            let res = b"HTTP/1.1 200 OK\r\nx-name: caf\xc3\xa9\r\ncontent-length: 0\r\n\r\n";
+            stream.write_all(res).unwrap();
+        });
+
+        let url = Url::parse(&format!("http://127.0.0.1:{}/", port))?;
+        let req = Request::new(http_types::Method::Get, url);
+        let err = IsahcClient::new().send(req).await.unwrap_err();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Protocol, "{}", err);
+        Ok(())
+    }
 }
@@ -28,14 +28,39 @@ pub mod wasm;
 #[cfg(feature = "native_client")]
 pub mod native;
 
-#[cfg_attr(feature = "docs", doc(cfg(h1_client)))]
-#[cfg(feature = "h1_client")]
+#[cfg_attr(
+    feature = "docs",
+    doc(cfg(any(h1_client_native_tls, h1_client_rustls)))
+)]
+#[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
 pub mod h1;
 
 #[cfg_attr(feature = "docs", doc(cfg(hyper_client)))]
 #[cfg(feature = "hyper_client")]
 pub mod hyper;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blocking;
+#[cfg_attr(feature = "docs", doc(cfg(cache)))]
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg_attr(feature = "docs", doc(cfg(cookies)))]
+#[cfg(feature = "cookies")]
+pub mod cookies;
+#[cfg_attr(feature = "docs", doc(cfg(decompress)))]
+#[cfg(feature = "decompress")]
+pub mod decompress;
+pub mod error;
+pub mod middleware;
+pub mod mock;
+#[cfg_attr(feature = "docs", doc(cfg(recorder)))]
+#[cfg(feature = "recorder")]
+pub mod recorder;
+pub mod redirect;
+#[cfg_attr(feature = "docs", doc(cfg(retry)))]
+#[cfg(all(feature = "retry", not(target_arch = "wasm32")))]
+pub mod retry;
+
 /// An HTTP Request type with a streaming body.
 pub type Request = http_types::Request;
 
@@ -56,8 +81,15 @@ pub use http_types;
 /// new requests. In order to enable this efficiently an `HttpClient` instance may want to be passed
 /// though middleware for one of its own requests, and in order to do so should be wrapped in an
 /// `Rc`/`Arc` to enable reference cloning.
+///
+/// The [`middleware`] module provides this: a `ClientStack` runs requests through a list of
+/// `Middleware` before sending them, and hands each of them a handle to the stack for new
+/// requests.
 pub trait HttpClient: std::fmt::Debug + Unpin + Send + Sync + 'static {
     /// Perform a request.
+    ///
+    /// Errors returned by the clients in this crate say what went wrong with an
+    /// [`error::ErrorKind`].
     fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>>;
 }
 
@@ -0,0 +1,245 @@
+//! Middleware, and a client that runs requests through it.
+
+use super::{Error, HttpClient, Request, Response};
+
+use futures::future::BoxFuture;
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Middleware that sees every request sent with a `ClientStack`, and the response to it.
+///
+/// Middleware can change the request before passing it on with `next.run`, change the response
+/// on the way back, or answer the request itself without calling `next` at all. `client` sends
+/// new requests through the whole stack, including this middleware.
+///
+/// # Examples
+///
+/// ```
+/// use futures::future::BoxFuture;
+/// use http_client::middleware::{ClientStack, Middleware, Next};
+/// use http_client::{Error, Request, Response};
+///
+/// /// Log the status of every response.
+/// #[derive(Debug)]
+/// struct Logger;
+///
+/// impl Middleware for Logger {
+///     fn handle<'a>(
+///         &'a self,
+///         req: Request,
+///         _client: ClientStack,
+///         next: Next<'a>,
+///     ) -> BoxFuture<'a, Result<Response, Error>> {
+///         Box::pin(async move {
+///             let url = req.url().clone();
+///             let res = next.run(req).await?;
+///             println!("{} {}", res.status(), url);
+///             Ok(res)
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware: Debug + Send + Sync + 'static {
+    /// Handle a request, passing it on to `next` or answering it.
+    fn handle<'a>(
+        &'a self,
+        req: Request,
+        client: ClientStack,
+        next: Next<'a>,
+    ) -> BoxFuture<'a, Result<Response, Error>>;
+}
+
+/// The rest of the stack after the current middleware.
+#[derive(Debug)]
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    client: &'a ClientStack,
+}
+
+impl<'a> Next<'a> {
+    /// Run the rest of the stack, and eventually the inner client, with `req`.
+    pub fn run(self, req: Request) -> BoxFuture<'a, Result<Response, Error>> {
+        match self.middleware.split_first() {
+            Some((current, rest)) => {
+                let next = Next {
+                    middleware: rest,
+                    client: self.client,
+                };
+                current.handle(req, self.client.clone(), next)
+            }
+            None => self.client.inner.send(req),
+        }
+    }
+}
+
+/// A client that runs requests through a stack of middleware before sending them with an inner
+/// client.
+///
+/// Middleware runs in the order it was added, so the first one sees requests first and responses
+/// last.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// # use futures::future::BoxFuture;
+/// # use http_client::middleware::{Middleware, Next};
+/// # use http_client::{Error, Response};
+/// # #[derive(Debug)]
+/// # struct Logger;
+/// # impl Middleware for Logger {
+/// #     fn handle<'a>(&'a self, req: Request, _: ClientStack, next: Next<'a>) -> BoxFuture<'a, Result<Response, Error>> {
+/// #         next.run(req)
+/// #     }
+/// # }
+/// use http_client::h1::H1Client;
+/// use http_client::middleware::ClientStack;
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+///
+/// let client = ClientStack::new(H1Client::new()).with(Logger);
+/// let res = client.send(Request::new(Method::Get, "http://example.com")).await?;
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientStack {
+    middleware: Arc<Vec<Arc<dyn Middleware>>>,
+    inner: Arc<dyn HttpClient>,
+}
+
+impl ClientStack {
+    /// Create a stack without middleware, sending requests with `inner`.
+    pub fn new(inner: impl HttpClient) -> Self {
+        Self {
+            middleware: Arc::new(Vec::new()),
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Add `middleware` to the end of the stack.
+    pub fn with(mut self, middleware: impl Middleware) -> Self {
+        Arc::make_mut(&mut self.middleware).push(Arc::new(middleware));
+        self
+    }
+
+    /// The middleware in the stack, in the order it runs.
+    pub fn middleware(&self) -> &[Arc<dyn Middleware>] {
+        &self.middleware
+    }
+}
+
+impl HttpClient for ClientStack {
+    fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let stack = self.clone();
+        Box::pin(async move {
+            let next = Next {
+                middleware: &stack.middleware,
+                client: &stack,
+            };
+            next.run(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+    use http_types::headers::AUTHORIZATION;
+    use http_types::{Method, StatusCode};
+
+    /// Append its name to the `x-trail` header of requests and responses.
+    #[derive(Debug)]
+    struct Trail(&'static str);
+
+    impl Middleware for Trail {
+        fn handle<'a>(
+            &'a self,
+            mut req: Request,
+            _client: ClientStack,
+            next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response, Error>> {
+            Box::pin(async move {
+                req.append_header("x-trail", self.0);
+                let mut res = next.run(req).await?;
+                res.append_header("x-trail", self.0);
+                Ok(res)
+            })
+        }
+    }
+
+    /// Fetch a token with a sub-request, and send it along with the original request.
+    #[derive(Debug)]
+    struct Token;
+
+    impl Middleware for Token {
+        fn handle<'a>(
+            &'a self,
+            mut req: Request,
+            client: ClientStack,
+            next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response, Error>> {
+            Box::pin(async move {
+                if req.url().path() != "/token" {
+                    let url = req.url().join("/token")?;
+                    let mut res = client.send(Request::new(Method::Post, url)).await?;
+                    let token = res.body_string().await?;
+                    req.insert_header(AUTHORIZATION, format!("Bearer {}", token));
+                }
+                next.run(req).await
+            })
+        }
+    }
+
+    fn trail(values: Option<&http_types::headers::HeaderValues>) -> Vec<&str> {
+        values.unwrap().iter().map(|v| v.as_str()).collect()
+    }
+
+    #[async_std::test]
+    async fn runs_middleware_in_order() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/")
+            .header("x-trail", "one")
+            .header("x-trail", "two")
+            .respond(StatusCode::Ok, "");
+        let client = ClientStack::new(mock.clone())
+            .with(Trail("one"))
+            .with(Trail("two"));
+        assert_eq!(client.middleware().len(), 2);
+
+        let res = client
+            .send(Request::new(Method::Get, "http://api.test/"))
+            .await?;
+        assert_eq!(trail(res.header("x-trail")), vec!["two", "one"]);
+        let received = mock.received();
+        assert_eq!(received[0].header("x-trail"), vec!["one", "two"]);
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn sends_sub_requests_through_the_stack() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Post, "/token")
+            .header("x-trail", "outer")
+            .respond(StatusCode::Ok, "secret");
+        mock.expect(Method::Get, "/users")
+            .header("authorization", "Bearer secret")
+            .respond(StatusCode::Ok, "ferris");
+        let client = ClientStack::new(mock.clone())
+            .with(Trail("outer"))
+            .with(Token);
+
+        let mut res = client
+            .send(Request::new(Method::Get, "http://api.test/users"))
+            .await?;
+        assert_eq!(res.body_string().await?, "ferris");
+        mock.verify().unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,515 @@
+//! A mock `HttpClient`, for testing code that depends on one.
+
+use super::{Error, HttpClient, Request, Response};
+
+use futures::future::BoxFuture;
+use http_types::url::Url;
+use http_types::{Method, StatusCode};
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A client that answers requests with responses set up in advance, without touching the
+/// network.
+///
+/// Each expectation matches requests by method and path, and optionally by query parameters,
+/// headers and body. Requests are answered by the first expectation that matches them and
+/// hasn't been called as often as it expects yet. Requests that no expectation matches fail
+/// with a `501 Not Implemented` error.
+///
+/// Once the code under test has run, `verify` checks that every expectation was called as often
+/// as it expected, and that no unexpected requests were made.
+///
+/// # Examples
+///
+/// ```
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::mock::MockClient;
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request, StatusCode};
+///
+/// let client = MockClient::new();
+/// client
+///     .expect(Method::Get, "/users")
+///     .query("id", "1")
+///     .times(1)
+///     .respond(StatusCode::Ok, r#"{"name":"Ferris"}"#);
+///
+/// let req = Request::new(Method::Get, "https://api.example.com/users?id=1");
+/// let mut res = client.send(req).await?;
+/// assert_eq!(res.body_string().await?, r#"{"name":"Ferris"}"#);
+///
+/// client.verify().unwrap();
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockClient {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    mocks: Vec<Mock>,
+    unexpected: Vec<ReceivedRequest>,
+}
+
+impl MockClient {
+    /// Create a new instance without any expectations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect requests with `method` to `path`.
+    ///
+    /// The expectation is added once a response is set with `respond` or `respond_with`.
+    pub fn expect(&self, method: Method, path: &str) -> MockBuilder {
+        MockBuilder {
+            client: self.clone(),
+            criteria: vec![Criterion::Method(method), Criterion::Path(path.to_owned())],
+            times: None,
+        }
+    }
+
+    /// The requests received so far, in order, whether they were expected or not.
+    pub fn received(&self) -> Vec<ReceivedRequest> {
+        let state = self.state.lock().unwrap();
+        let mut received: Vec<_> = state
+            .mocks
+            .iter()
+            .flat_map(|mock| mock.received.iter().cloned())
+            .chain(state.unexpected.iter().cloned())
+            .collect();
+        received.sort_by_key(|req| req.seq);
+        received
+    }
+
+    /// Check that every expectation was met and no unexpected requests were received.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let state = self.state.lock().unwrap();
+        let mut problems = Vec::new();
+        for mock in &state.mocks {
+            let calls = mock.received.len();
+            let met = match mock.times {
+                Some(times) => calls == times,
+                None => calls > 0,
+            };
+            if !met {
+                let expected = match mock.times {
+                    Some(times) => format!("{} time(s)", times),
+                    None => "at least once".to_owned(),
+                };
+                problems.push(format!(
+                    "expected {} to be called {}, but it was called {} time(s)",
+                    mock, expected, calls
+                ));
+            }
+        }
+        for req in &state.unexpected {
+            problems.push(unexpected(req, &state.mocks));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(VerifyError { problems })
+        }
+    }
+
+    fn register(&self, mock: Mock) {
+        self.state.lock().unwrap().mocks.push(mock);
+    }
+}
+
+impl HttpClient for MockClient {
+    fn send(&self, mut req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let state = self.state.clone();
+        Box::pin(async move {
+            let body = req.body_bytes().await?;
+            let mut state = state.lock().unwrap();
+            let seq = state.mocks.iter().map(|m| m.received.len()).sum::<usize>()
+                + state.unexpected.len();
+            let received = ReceivedRequest {
+                seq,
+                method: req.method(),
+                url: req.url().clone(),
+                headers: req
+                    .iter()
+                    .map(|(name, values)| {
+                        let values = values.iter().map(|v| v.as_str().to_owned()).collect();
+                        (name.as_str().to_owned(), values)
+                    })
+                    .collect(),
+                body,
+            };
+
+            let mock = state.mocks.iter_mut().find(|mock| {
+                mock.times.is_none_or(|times| mock.received.len() < times)
+                    && mock.criteria.iter().all(|c| c.check(&received).is_ok())
+            });
+            match mock {
+                Some(mock) => {
+                    mock.received.push(received.clone());
+                    Ok((mock.responder)(&received))
+                }
+                None => {
+                    let msg = unexpected(&received, &state.mocks);
+                    state.unexpected.push(received);
+                    Err(Error::from_str(StatusCode::NotImplemented, msg))
+                }
+            }
+        })
+    }
+}
+
+/// A request received by a `MockClient`.
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    seq: usize,
+    method: Method,
+    url: Url,
+    headers: Vec<(String, Vec<String>)>,
+    body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// The request method.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The request URL.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The values of the header `name`.
+    pub fn header(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, values)| values.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// The request body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    fn query(&self, name: &str) -> Vec<String> {
+        self.url
+            .query_pairs()
+            .filter(|(n, _)| n == name)
+            .map(|(_, value)| value.into_owned())
+            .collect()
+    }
+}
+
+impl fmt::Display for ReceivedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.url)
+    }
+}
+
+/// A builder for an expectation of a `MockClient`.
+#[must_use = "the expectation is only added once a response is set"]
+pub struct MockBuilder {
+    client: MockClient,
+    criteria: Vec<Criterion>,
+    times: Option<usize>,
+}
+
+impl fmt::Debug for MockBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockBuilder")
+            .field("criteria", &self.criteria)
+            .field("times", &self.times)
+            .finish()
+    }
+}
+
+impl MockBuilder {
+    /// Only match requests with the query parameter `name` set to `value`.
+    pub fn query(mut self, name: &str, value: &str) -> Self {
+        self.criteria
+            .push(Criterion::Query(name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Only match requests with a `name` header with the value `value`.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.criteria
+            .push(Criterion::Header(name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Only match requests with exactly this body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.criteria.push(Criterion::Body(body.into()));
+        self
+    }
+
+    /// Only match requests whose body satisfies `predicate`.
+    pub fn body_matches<F>(mut self, description: &str, predicate: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.criteria.push(Criterion::BodyMatches(
+            description.to_owned(),
+            Box::new(predicate),
+        ));
+        self
+    }
+
+    /// Expect exactly `times` matching requests, instead of at least one.
+    ///
+    /// Once it has been called `times` times, the expectation no longer matches requests.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// Answer matching requests with `status` and `body`.
+    pub fn respond(self, status: StatusCode, body: impl Into<String>) {
+        let body = body.into();
+        self.respond_with(move |_| {
+            let mut res = Response::new(status);
+            res.set_body(body.as_str());
+            res
+        })
+    }
+
+    /// Answer matching requests with the response `responder` creates for them.
+    pub fn respond_with<F>(self, responder: F)
+    where
+        F: Fn(&ReceivedRequest) -> Response + Send + Sync + 'static,
+    {
+        self.client.register(Mock {
+            criteria: self.criteria,
+            times: self.times,
+            responder: Box::new(responder),
+            received: Vec::new(),
+        });
+    }
+}
+
+type Responder = Box<dyn Fn(&ReceivedRequest) -> Response + Send + Sync>;
+
+type BodyPredicate = Box<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+struct Mock {
+    criteria: Vec<Criterion>,
+    times: Option<usize>,
+    responder: Responder,
+    received: Vec<ReceivedRequest>,
+}
+
+impl fmt::Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mock")
+            .field("criteria", &self.criteria)
+            .field("times", &self.times)
+            .field("received", &self.received.len())
+            .finish()
+    }
+}
+
+impl fmt::Display for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let criteria: Vec<_> = self.criteria.iter().map(ToString::to_string).collect();
+        f.write_str(&criteria.join(", "))
+    }
+}
+
+enum Criterion {
+    Method(Method),
+    Path(String),
+    Query(String, String),
+    Header(String, String),
+    Body(Vec<u8>),
+    BodyMatches(String, BodyPredicate),
+}
+
+impl Criterion {
+    /// Check `req` against this criterion, explaining how it differs if it doesn't match.
+    fn check(&self, req: &ReceivedRequest) -> Result<(), String> {
+        let differs = |expected: &dyn fmt::Debug, got: &dyn fmt::Debug| {
+            Err(format!("{}: expected {:?}, got {:?}", self, expected, got))
+        };
+        match self {
+            Criterion::Method(method) if *method != req.method => differs(method, &req.method),
+            Criterion::Path(path) if path != req.url.path() => differs(path, &req.url.path()),
+            Criterion::Query(name, value) => {
+                let values = req.query(name);
+                match values.contains(value) {
+                    true => Ok(()),
+                    false => differs(value, &values),
+                }
+            }
+            Criterion::Header(name, value) => {
+                let values = req.header(name);
+                match values.contains(&value.as_str()) {
+                    true => Ok(()),
+                    false => differs(value, &values),
+                }
+            }
+            Criterion::Body(body) if *body != req.body => differs(
+                &String::from_utf8_lossy(body),
+                &String::from_utf8_lossy(&req.body),
+            ),
+            Criterion::BodyMatches(_, predicate) if !predicate(&req.body) => Err(format!(
+                "{}: got {:?}",
+                self,
+                String::from_utf8_lossy(&req.body)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Criterion::Method(method) => write!(f, "method {}", method),
+            Criterion::Path(path) => write!(f, "path {}", path),
+            Criterion::Query(name, value) => write!(f, "query {}={}", name, value),
+            Criterion::Header(name, value) => write!(f, "header {}: {}", name, value),
+            Criterion::Body(body) => write!(f, "body {:?}", String::from_utf8_lossy(body)),
+            Criterion::BodyMatches(description, _) => write!(f, "body {}", description),
+        }
+    }
+}
+
+/// Describe an unexpected request, and how it differs from the expectation closest to it.
+fn unexpected(req: &ReceivedRequest, mocks: &[Mock]) -> String {
+    let mut msg = format!("unexpected request {}", req);
+    let closest = mocks
+        .iter()
+        .map(|mock| {
+            let diffs: Vec<_> = mock
+                .criteria
+                .iter()
+                .filter_map(|c| c.check(req).err())
+                .collect();
+            (mock, diffs)
+        })
+        .min_by_key(|(_, diffs)| diffs.len());
+    match closest {
+        Some((mock, diffs)) if diffs.is_empty() => {
+            msg.push_str(&format!(
+                "\n  {} was already called {} time(s)",
+                mock,
+                mock.received.len()
+            ));
+        }
+        Some((mock, diffs)) => {
+            msg.push_str(&format!("\n  closest expectation: {}", mock));
+            for diff in diffs {
+                msg.push_str(&format!("\n    - {}", diff));
+            }
+        }
+        None => msg.push_str("\n  no expectations were set"),
+    }
+    msg
+}
+
+/// The ways in which a `MockClient` didn't receive the requests it expected.
+#[derive(Debug)]
+pub struct VerifyError {
+    problems: Vec<String>,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mock client expectations were not met:")?;
+        for problem in &self.problems {
+            writeln!(f, "- {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn answers_matching_requests() -> http_types::Result<()> {
+        let client = MockClient::new();
+        client
+            .expect(Method::Post, "/users")
+            .header("content-type", "application/json")
+            .body_matches("containing a name", |body| body.starts_with(b"{\"name\""))
+            .respond_with(|req| {
+                let mut res = Response::new(StatusCode::Created);
+                res.set_body(req.body().to_vec());
+                res
+            });
+        client
+            .expect(Method::Get, "/users")
+            .query("id", "1")
+            .times(2)
+            .respond(StatusCode::Ok, "ferris");
+
+        let mut req = Request::new(Method::Post, "http://api.test/users");
+        req.insert_header("content-type", "application/json");
+        req.set_body(r#"{"name":"ferris"}"#);
+        let mut res = client.send(req).await?;
+        assert_eq!(res.status(), StatusCode::Created);
+        assert_eq!(res.body_string().await?, r#"{"name":"ferris"}"#);
+
+        for _ in 0..2 {
+            let req = Request::new(Method::Get, "http://api.test/users?page=2&id=1");
+            let mut res = client.send(req).await?;
+            assert_eq!(res.body_string().await?, "ferris");
+        }
+
+        client.verify().unwrap();
+        let received: Vec<_> = client.received().iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            received,
+            vec![
+                "POST http://api.test/users",
+                "GET http://api.test/users?page=2&id=1",
+                "GET http://api.test/users?page=2&id=1",
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn reports_unmet_and_unexpected_requests() {
+        let client = MockClient::new();
+        client
+            .expect(Method::Get, "/users")
+            .query("id", "1")
+            .times(1)
+            .respond(StatusCode::Ok, "ferris");
+        client
+            .expect(Method::Delete, "/users")
+            .respond(StatusCode::NoContent, "");
+
+        let req = Request::new(Method::Get, "http://api.test/users?id=2");
+        let err = client.send(req).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+
+        let report = client.verify().unwrap_err().to_string();
+        assert_eq!(
+            report,
+            "mock client expectations were not met:\n\
+             - expected method GET, path /users, query id=1 to be called 1 time(s), but it was called 0 time(s)\n\
+             - expected method DELETE, path /users to be called at least once, but it was called 0 time(s)\n\
+             - unexpected request GET http://api.test/users?id=2\n  \
+             closest expectation: method GET, path /users, query id=1\n    \
+             - query id=1: expected \"1\", got [\"2\"]\n"
+        );
+    }
+}
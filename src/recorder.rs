@@ -0,0 +1,538 @@
+//! Recording and replaying requests, for any `HttpClient`.
+
+use super::{Body, Error, HttpClient, Request, Response};
+
+use futures::future::BoxFuture;
+use futures::lock::Mutex as AsyncMutex;
+use http_types::headers::{HeaderName, AUTHORIZATION, CONTENT_TYPE, PROXY_AUTHORIZATION};
+use http_types::url::Url;
+use http_types::StatusCode;
+use serde_json::{json, Value};
+
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fs, io};
+
+/// The value recorded in place of redacted headers.
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether a `Recorder` records new interactions or replays recorded ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Send requests with the inner client, and write each request and its response to the
+    /// cassette, replacing what it held before.
+    Record,
+    /// Answer requests from the cassette, without sending them.
+    Replay,
+}
+
+/// The parts of a request compared to find its recorded interaction when replaying.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchOn {
+    /// The request method.
+    Method,
+    /// The whole URL.
+    Url,
+    /// The path of the URL.
+    Path,
+    /// The query of the URL.
+    Query,
+    /// The values of a header.
+    Header(HeaderName),
+    /// The request body.
+    Body,
+}
+
+/// A client that records requests and their responses to a cassette file, and replays them from
+/// it later.
+///
+/// This makes integration tests repeatable without network access: they run once in
+/// `Mode::Record` against the real service, and afterwards in `Mode::Replay` against the
+/// cassette. The cassette is a JSON file, rewritten after each recorded interaction.
+///
+/// When replaying, each recorded interaction answers one request, found by comparing the parts
+/// set with `match_on` (the method and URL by default). Requests without a recorded interaction
+/// are sent with the inner client, or fail with a `501 Not Implemented` error in strict mode.
+///
+/// The values of the `Authorization` and `Proxy-Authorization` headers, and of any header passed
+/// to `redact`, are never written to the cassette.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::h1::H1Client;
+/// use http_client::recorder::{Mode, Recorder};
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+///
+/// let mode = match std::env::var("RECORD") {
+///     Ok(_) => Mode::Record,
+///     Err(_) => Mode::Replay,
+/// };
+/// let client = Recorder::new(H1Client::new(), "tests/cassettes/example.json", mode)?
+///     .redact("x-api-key")
+///     .strict(true);
+/// let res = client.send(Request::new(Method::Get, "http://example.com")).await?;
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct Recorder<C> {
+    inner: Arc<C>,
+    path: Arc<PathBuf>,
+    mode: Mode,
+    match_on: Arc<Vec<MatchOn>>,
+    redact: Arc<Vec<HeaderName>>,
+    strict: bool,
+    cassette: Arc<Mutex<Cassette>>,
+    /// Held while the cassette is written, so that writes land in the order they were made.
+    writes: Arc<AsyncMutex<()>>,
+}
+
+impl<C> Clone for Recorder<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            path: self.path.clone(),
+            mode: self.mode,
+            match_on: self.match_on.clone(),
+            redact: self.redact.clone(),
+            strict: self.strict,
+            cassette: self.cassette.clone(),
+            writes: self.writes.clone(),
+        }
+    }
+}
+
+impl<C: HttpClient> Recorder<C> {
+    /// Record requests sent with `inner` to the cassette at `path`, or replay them from it.
+    ///
+    /// In `Mode::Replay`, the cassette is read right away.
+    pub fn new(inner: C, path: impl AsRef<Path>, mode: Mode) -> io::Result<Self> {
+        let cassette = match mode {
+            Mode::Record => Cassette::default(),
+            Mode::Replay => {
+                let json = fs::read(path.as_ref())?;
+                let json: Value = serde_json::from_slice(&json)?;
+                Cassette::from_json(&json).ok_or_else(|| {
+                    let msg = format!("invalid cassette {}", path.as_ref().display());
+                    io::Error::new(io::ErrorKind::InvalidData, msg)
+                })?
+            }
+        };
+        Ok(Self {
+            inner: Arc::new(inner),
+            path: Arc::new(path.as_ref().to_owned()),
+            mode,
+            match_on: Arc::new(vec![MatchOn::Method, MatchOn::Url]),
+            redact: Arc::new(vec![AUTHORIZATION, PROXY_AUTHORIZATION]),
+            strict: false,
+            cassette: Arc::new(Mutex::new(cassette)),
+            writes: Arc::new(AsyncMutex::new(())),
+        })
+    }
+
+    /// Set the parts of a request that must equal those of a recorded one for it to be replayed.
+    pub fn match_on(mut self, rules: impl IntoIterator<Item = MatchOn>) -> Self {
+        self.match_on = Arc::new(rules.into_iter().collect());
+        self
+    }
+
+    /// Never write the values of the header `name` to the cassette.
+    pub fn redact(mut self, name: impl Into<HeaderName>) -> Self {
+        Arc::make_mut(&mut self.redact).push(name.into());
+        self
+    }
+
+    /// Fail requests without a recorded interaction when replaying, instead of sending them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
+impl<C: HttpClient> HttpClient for Recorder<C> {
+    fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let client = self.clone();
+        Box::pin(async move { client.handle(req).await })
+    }
+}
+
+impl<C: HttpClient> Recorder<C> {
+    async fn handle(&self, mut req: Request) -> Result<Response, Error> {
+        let body = req.take_body().into_bytes().await?;
+        let recorded = RecordedRequest {
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            headers: self.headers(req.iter()),
+            body: RecordedBody::from(&body),
+        };
+        req.set_body(Body::from_bytes(body));
+
+        if self.mode == Mode::Replay {
+            if let Some(res) = self.replay(&recorded) {
+                return res;
+            }
+            if self.strict {
+                return Err(Error::from_str(
+                    StatusCode::NotImplemented,
+                    format!(
+                        "no recorded interaction for {} {} in {}",
+                        recorded.method,
+                        recorded.url,
+                        self.path.display()
+                    ),
+                ));
+            }
+            log::debug!(
+                "{} {} wasn't recorded, sending it",
+                recorded.method,
+                recorded.url
+            );
+            return self.inner.send(req).await;
+        }
+
+        let mut res = self.inner.send(req).await?;
+        let body = res.take_body().into_bytes().await?;
+        let response = RecordedResponse {
+            status: res.status() as u16,
+            headers: self.headers(res.iter()),
+            body: RecordedBody::from(&body),
+        };
+        res.set_body(Body::from_bytes(body));
+
+        let _write = self.writes.lock().await;
+        let json = {
+            let mut cassette = self.cassette.lock().unwrap();
+            cassette.interactions.push(Interaction {
+                request: recorded,
+                response,
+                played: false,
+            });
+            serde_json::to_vec_pretty(&cassette.to_json())?
+        };
+        // Write on a blocking thread pool, so the executor isn't held up by the file system.
+        let path = self.path.clone();
+        blocking::unblock(move || fs::write(&*path, json)).await?;
+        Ok(res)
+    }
+
+    /// Answer `req` with the first matching interaction that wasn't replayed yet.
+    fn replay(&self, req: &RecordedRequest) -> Option<Result<Response, Error>> {
+        let mut cassette = self.cassette.lock().unwrap();
+        let interaction = cassette
+            .interactions
+            .iter_mut()
+            .find(|i| !i.played && self.matches(&i.request, req))?;
+        interaction.played = true;
+        Some(interaction.response.to_response())
+    }
+
+    /// The headers as they're written to the cassette.
+    fn headers<'a>(
+        &self,
+        headers: impl Iterator<Item = (&'a HeaderName, &'a http_types::headers::HeaderValues)>,
+    ) -> Vec<(String, String)> {
+        let mut recorded = Vec::new();
+        for (name, values) in headers {
+            for value in values {
+                let value = match self.redact.contains(name) {
+                    true => REDACTED,
+                    false => value.as_str(),
+                };
+                recorded.push((name.as_str().to_owned(), value.to_owned()));
+            }
+        }
+        recorded
+    }
+
+    fn matches(&self, recorded: &RecordedRequest, req: &RecordedRequest) -> bool {
+        let (recorded_url, url) = match (Url::parse(&recorded.url), Url::parse(&req.url)) {
+            (Ok(recorded_url), Ok(url)) => (recorded_url, url),
+            _ => return false,
+        };
+        self.match_on.iter().all(|rule| match rule {
+            MatchOn::Method => recorded.method == req.method,
+            MatchOn::Url => recorded_url == url,
+            MatchOn::Path => recorded_url.path() == url.path(),
+            MatchOn::Query => recorded_url.query() == url.query(),
+            MatchOn::Header(name) => recorded.header(name) == req.header(name),
+            MatchOn::Body => recorded.body == req.body,
+        })
+    }
+}
+
+/// The interactions recorded to a cassette file.
+#[derive(Debug, Default)]
+struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    fn to_json(&self) -> Value {
+        let interactions: Vec<_> = self.interactions.iter().map(Interaction::to_json).collect();
+        json!({ "interactions": interactions })
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        let interactions = json["interactions"]
+            .as_array()?
+            .iter()
+            .map(Interaction::from_json)
+            .collect::<Option<_>>()?;
+        Some(Self { interactions })
+    }
+}
+
+#[derive(Debug)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+    /// Whether the interaction was already replayed.
+    played: bool,
+}
+
+impl Interaction {
+    fn to_json(&self) -> Value {
+        let (req, res) = (&self.request, &self.response);
+        json!({
+            "request": {
+                "method": req.method,
+                "url": req.url,
+                "headers": headers_to_json(&req.headers),
+                "body": req.body.to_json(),
+            },
+            "response": {
+                "status": res.status,
+                "headers": headers_to_json(&res.headers),
+                "body": res.body.to_json(),
+            },
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        let (req, res) = (&json["request"], &json["response"]);
+        let request = RecordedRequest {
+            method: req["method"].as_str()?.to_owned(),
+            url: req["url"].as_str()?.to_owned(),
+            headers: headers_from_json(&req["headers"])?,
+            body: RecordedBody::from_json(&req["body"])?,
+        };
+        let response = RecordedResponse {
+            status: u16::try_from(res["status"].as_u64()?).ok()?,
+            headers: headers_from_json(&res["headers"])?,
+            body: RecordedBody::from_json(&res["body"])?,
+        };
+        Some(Self {
+            request,
+            response,
+            played: false,
+        })
+    }
+}
+
+fn headers_to_json(headers: &[(String, String)]) -> Value {
+    headers
+        .iter()
+        .map(|(name, value)| json!([name, value]))
+        .collect()
+}
+
+fn headers_from_json(json: &Value) -> Option<Vec<(String, String)>> {
+    json.as_array()?
+        .iter()
+        .map(|header| {
+            let name = header.get(0)?.as_str()?;
+            let value = header.get(1)?.as_str()?;
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+impl RecordedRequest {
+    fn header(&self, name: &HeaderName) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<Response, Error> {
+        let status = StatusCode::try_from(self.status)?;
+        let mut res = Response::new(status);
+        for (name, value) in &self.headers {
+            res.append_header(name.as_str(), value.as_str());
+        }
+        let has_content_type = res.header(CONTENT_TYPE).is_some();
+        res.set_body(Body::from_bytes(self.body.to_bytes()?));
+        if !has_content_type {
+            res.remove_header(CONTENT_TYPE);
+        }
+        Ok(res)
+    }
+}
+
+/// A body, as text when it's valid UTF-8 and in base64 otherwise.
+#[derive(Debug, PartialEq, Eq)]
+enum RecordedBody {
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    fn from(bytes: &[u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => RecordedBody::Text(text.to_owned()),
+            Err(_) => RecordedBody::Base64(base64::encode(bytes)),
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        match self {
+            RecordedBody::Text(text) => Ok(text.clone().into_bytes()),
+            RecordedBody::Base64(encoded) => Ok(base64::decode(encoded)?),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            RecordedBody::Text(text) => json!({ "text": text }),
+            RecordedBody::Base64(encoded) => json!({ "base64": encoded }),
+        }
+    }
+
+    fn from_json(json: &Value) -> Option<Self> {
+        match (json["text"].as_str(), json["base64"].as_str()) {
+            (Some(text), None) => Some(RecordedBody::Text(text.to_owned())),
+            (None, Some(encoded)) => Some(RecordedBody::Base64(encoded.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+    use http_types::Method;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn cassette() -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::SeqCst);
+        std::env::temp_dir().join(format!("http-client-{}-{}.json", std::process::id(), n))
+    }
+
+    fn request(method: Method, url: &str, body: &[u8]) -> Request {
+        let mut req = Request::new(method, url);
+        req.insert_header(AUTHORIZATION, "Bearer secret");
+        req.insert_header("x-api-key", "key");
+        req.set_body(body);
+        req
+    }
+
+    #[async_std::test]
+    async fn records_and_replays() -> http_types::Result<()> {
+        let path = cassette();
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/users")
+            .respond(StatusCode::Ok, "ferris");
+        mock.expect(Method::Post, "/upload").respond_with(|req| {
+            let mut res = Response::new(StatusCode::Created);
+            res.set_body(req.body().to_vec());
+            res
+        });
+
+        let client = Recorder::new(mock.clone(), &path, Mode::Record)?.redact("x-api-key");
+        let mut res = client
+            .send(request(Method::Get, "http://api.test/users", b""))
+            .await?;
+        assert_eq!(res.body_string().await?, "ferris");
+        let binary = [0xff, 0x00, 0xfe];
+        let mut res = client
+            .send(request(Method::Post, "http://api.test/upload", &binary))
+            .await?;
+        assert_eq!(res.body_bytes().await?, binary);
+        mock.verify().unwrap();
+
+        let json = fs::read_to_string(&path)?;
+        assert!(
+            !json.contains("secret") && !json.contains("\"key\""),
+            "{}",
+            json
+        );
+        assert!(json.contains(&base64::encode(binary)), "{}", json);
+
+        let mock = MockClient::new();
+        let client = Recorder::new(mock.clone(), &path, Mode::Replay)?.strict(true);
+        let mut res = client
+            .send(request(Method::Post, "http://api.test/upload", b""))
+            .await?;
+        assert_eq!(res.status(), StatusCode::Created);
+        assert_eq!(res.body_bytes().await?, binary);
+        let mut res = client
+            .send(request(Method::Get, "http://api.test/users", b""))
+            .await?;
+        assert_eq!(res.body_string().await?, "ferris");
+        assert!(mock.received().is_empty());
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn applies_matching_rules_and_strict_mode() -> http_types::Result<()> {
+        let path = cassette();
+        let mock = MockClient::new();
+        mock.expect(Method::Post, "/search")
+            .respond(StatusCode::Ok, "results");
+        let client = Recorder::new(mock, &path, Mode::Record)?;
+        let req = request(Method::Post, "http://api.test/search?page=1", b"query");
+        client.send(req).await?;
+
+        let mock = MockClient::new();
+        let client = Recorder::new(mock.clone(), &path, Mode::Replay)?
+            .match_on(vec![MatchOn::Method, MatchOn::Path, MatchOn::Body])
+            .strict(true);
+        let req = request(Method::Post, "http://api.test/search?page=2", b"other");
+        let err = client.send(req).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+        let req = request(Method::Post, "http://api.test/search?page=2", b"query");
+        assert_eq!(client.send(req).await?.status(), StatusCode::Ok);
+
+        // Without strict mode, unrecorded requests are sent instead.
+        mock.expect(Method::Post, "/search")
+            .respond(StatusCode::Accepted, "");
+        let client = Recorder::new(mock.clone(), &path, Mode::Replay)?;
+        let req = request(Method::Post, "http://api.test/search?page=2", b"query");
+        assert_eq!(client.send(req).await?.status(), StatusCode::Accepted);
+        mock.verify().unwrap();
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
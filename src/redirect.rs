@@ -0,0 +1,354 @@
+//! Following redirects, for any `HttpClient`.
+
+use super::{Body, Error, HttpClient, Request, Response};
+
+use futures::future::BoxFuture;
+use http_types::headers::{
+    HeaderName, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST,
+    LOCATION, TRANSFER_ENCODING,
+};
+use http_types::url::Url;
+use http_types::{Method, StatusCode};
+
+use std::sync::Arc;
+
+/// The largest request body that's buffered so it can be sent again after a `307` or `308`.
+///
+/// Redirects of requests with larger or unknown-length bodies are not followed, and are returned
+/// as they are instead.
+const MAX_REPLAY_BODY: usize = 1024 * 1024;
+
+/// A client that follows redirects.
+///
+/// `301` and `302` redirects of `POST` requests, and all `303` redirects, are followed with a
+/// `GET` request without a body. Other `301` and `302` redirects, as well as `307` and `308`
+/// redirects, are followed with the same method and body. The `Authorization` and `Cookie`
+/// headers are only sent again when a redirect stays on the same origin.
+///
+/// The final response carries a `RedirectChain` extension with every URL that was requested.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::h1::H1Client;
+/// use http_client::redirect::{Redirect, RedirectChain};
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+///
+/// let client = Redirect::new(H1Client::new()).max_redirects(5);
+/// let res = client.send(Request::new(Method::Get, "http://example.com")).await?;
+/// let chain: &RedirectChain = res.ext().get().unwrap();
+/// println!("ended up at {}", chain.urls().last().unwrap());
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct Redirect<C> {
+    inner: Arc<C>,
+    max_redirects: usize,
+}
+
+impl<C> Clone for Redirect<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            max_redirects: self.max_redirects,
+        }
+    }
+}
+
+impl<C: HttpClient> Redirect<C> {
+    /// Follow up to 10 redirects of the requests sent with `inner`.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            max_redirects: 10,
+        }
+    }
+
+    /// Set the number of redirects to follow before giving up with an error.
+    ///
+    /// With `0`, redirects are returned rather than followed.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+}
+
+/// The URLs requested on the way to a response, in order.
+///
+/// The first URL is the one of the original request, and the last is the one of the final
+/// response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedirectChain {
+    urls: Vec<Url>,
+}
+
+impl RedirectChain {
+    /// The URLs requested on the way to the response.
+    pub fn urls(&self) -> &[Url] {
+        &self.urls
+    }
+
+    /// The number of redirects that were followed.
+    pub fn redirects(&self) -> usize {
+        self.urls.len() - 1
+    }
+}
+
+impl<C: HttpClient> HttpClient for Redirect<C> {
+    fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let client = self.clone();
+        Box::pin(async move { client.follow(req).await })
+    }
+}
+
+impl<C: HttpClient> Redirect<C> {
+    async fn follow(&self, mut req: Request) -> Result<Response, Error> {
+        let mut urls = vec![req.url().clone()];
+        loop {
+            // Keep a copy of the body around in case it has to be sent again.
+            let body = match req.len() {
+                Some(len) if len <= MAX_REPLAY_BODY => {
+                    let bytes = req.take_body().into_bytes().await?;
+                    req.set_body(bytes.clone());
+                    Some(bytes)
+                }
+                _ => None,
+            };
+            let next = NextRequest::of(&req, body);
+
+            let mut res = self.inner.send(req).await?;
+            let next = match next.redirect(&res) {
+                Some(next) if self.max_redirects > 0 => next,
+                _ => {
+                    res.ext_mut().insert(RedirectChain { urls });
+                    return Ok(res);
+                }
+            };
+            if urls.len() > self.max_redirects {
+                return Err(Error::from_str(
+                    StatusCode::LoopDetected,
+                    format!("too many redirects (max {})", self.max_redirects),
+                ));
+            }
+            log::trace!("following {} redirect to {}", res.status(), next.url);
+            urls.push(next.url.clone());
+            req = next.into_request();
+        }
+    }
+}
+
+/// What's needed to send a request again to another URL.
+struct NextRequest {
+    method: Method,
+    url: Url,
+    headers: Vec<(HeaderName, Vec<http_types::headers::HeaderValue>)>,
+    body: Option<Vec<u8>>,
+}
+
+impl NextRequest {
+    fn of(req: &Request, body: Option<Vec<u8>>) -> Self {
+        Self {
+            method: req.method(),
+            url: req.url().clone(),
+            headers: req
+                .iter()
+                .map(|(name, values)| (name.clone(), values.iter().cloned().collect()))
+                .collect(),
+            body,
+        }
+    }
+
+    /// Turn this into the request following the redirect `res`, if it is one that can be
+    /// followed.
+    fn redirect(mut self, res: &Response) -> Option<Self> {
+        let status = res.status();
+        let rewrite = match status {
+            StatusCode::MovedPermanently | StatusCode::Found => self.method == Method::Post,
+            StatusCode::SeeOther => self.method != Method::Head,
+            StatusCode::TemporaryRedirect | StatusCode::PermanentRedirect => false,
+            _ => return None,
+        };
+        let location = res.header(LOCATION)?.last().as_str();
+        let url = match self.url.join(location) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+            _ => {
+                log::debug!("not following redirect to invalid location {:?}", location);
+                return None;
+            }
+        };
+
+        if rewrite {
+            self.method = Method::Get;
+            self.body = Some(Vec::new());
+            let content = [
+                CONTENT_LENGTH,
+                CONTENT_TYPE,
+                CONTENT_ENCODING,
+                TRANSFER_ENCODING,
+            ];
+            self.headers.retain(|(name, _)| !content.contains(name));
+        } else if self.body.is_none() {
+            log::debug!("not following redirect: the request body can't be sent again");
+            return None;
+        }
+
+        if url.origin() != self.url.origin() {
+            let credentials = [AUTHORIZATION, COOKIE, HOST];
+            self.headers.retain(|(name, _)| !credentials.contains(name));
+        }
+        self.url = url;
+        Some(self)
+    }
+
+    fn into_request(self) -> Request {
+        let mut req = Request::new(self.method, self.url);
+        for (name, values) in self.headers {
+            for value in values {
+                req.append_header(&name, value);
+            }
+        }
+        if let Some(body) = self.body {
+            if !body.is_empty() {
+                req.set_body(Body::from_bytes(body));
+            }
+        }
+        req
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::sync::Mutex;
+
+    /// The method, URL, `Authorization` header and body of a request.
+    type Seen = (Method, String, Option<String>, String);
+
+    /// A server behind a fake client, answering requests by path.
+    #[derive(Debug, Default)]
+    struct Fake {
+        seen: Arc<Mutex<Vec<Seen>>>,
+    }
+
+    impl HttpClient for Fake {
+        fn send(&self, mut req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+            let seen = self.seen.clone();
+            Box::pin(async move {
+                let auth = req.header(AUTHORIZATION).map(|h| h.as_str().to_owned());
+                let body = req.body_string().await?;
+                let url = req.url().to_string();
+                seen.lock().unwrap().push((req.method(), url, auth, body));
+
+                let path = req.url().path().trim_start_matches('/').to_owned();
+                let mut parts = path.splitn(2, '/');
+                let res = match (parts.next().unwrap(), parts.next()) {
+                    ("ok", _) => Response::new(StatusCode::Ok),
+                    ("loop", _) => redirect(StatusCode::Found, "/loop"),
+                    (code, Some(location)) => {
+                        let code: u16 = code.parse()?;
+                        redirect(code.try_into()?, &percent_decode(location))
+                    }
+                    _ => Response::new(StatusCode::NotFound),
+                };
+                Ok(res)
+            })
+        }
+    }
+
+    fn redirect(status: StatusCode, location: &str) -> Response {
+        let mut res = Response::new(status);
+        res.insert_header(LOCATION, location);
+        res
+    }
+
+    fn percent_decode(s: &str) -> String {
+        s.replace("%3A", ":").replace("%2F", "/")
+    }
+
+    fn post(url: &str) -> Request {
+        let mut req = Request::new(Method::Post, url);
+        req.insert_header(AUTHORIZATION, "Bearer secret");
+        req.set_body("payload");
+        req
+    }
+
+    #[async_std::test]
+    async fn rewrites_methods_by_status() -> http_types::Result<()> {
+        let cases = [
+            (301, Method::Get, ""),
+            (302, Method::Get, ""),
+            (303, Method::Get, ""),
+            (307, Method::Post, "payload"),
+            (308, Method::Post, "payload"),
+        ];
+        for (code, method, body) in cases.iter() {
+            let fake = Fake::default();
+            let seen = fake.seen.clone();
+            let client = Redirect::new(fake);
+            let url = format!("http://example.com/{}/%2Fok", code);
+            let res = client.send(post(&url)).await?;
+            assert_eq!(res.status(), StatusCode::Ok);
+
+            let seen = seen.lock().unwrap();
+            let (seen_method, seen_url, _, seen_body) = &seen[1];
+            assert_eq!(
+                (seen_method, seen_body.as_str()),
+                (method, *body),
+                "{}",
+                code
+            );
+            assert_eq!(seen_url, "http://example.com/ok");
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn strips_credentials_across_origins() -> http_types::Result<()> {
+        let fake = Fake::default();
+        let seen = fake.seen.clone();
+        let client = Redirect::new(fake);
+        let url = "http://example.com/307/%2F307%2Fhttp%3A%2F%2Fother.example%2Fok";
+        let res = client.send(post(url)).await?;
+
+        let auths: Vec<_> = seen.lock().unwrap().iter().map(|s| s.2.clone()).collect();
+        let secret = Some("Bearer secret".to_owned());
+        assert_eq!(auths, vec![secret.clone(), secret, None]);
+
+        let chain: &RedirectChain = res.ext().get().unwrap();
+        let urls: Vec<_> = chain.urls().iter().map(Url::as_str).collect();
+        assert_eq!(
+            urls,
+            vec![
+                url,
+                "http://example.com/307/http://other.example/ok",
+                "http://other.example/ok",
+            ]
+        );
+        assert_eq!(chain.redirects(), 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn gives_up_after_max_redirects() -> http_types::Result<()> {
+        let fake = Fake::default();
+        let seen = fake.seen.clone();
+        let client = Redirect::new(fake).max_redirects(3);
+        let req = Request::new(Method::Get, "http://example.com/loop");
+        let err = client.send(req).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::LoopDetected);
+        assert_eq!(seen.lock().unwrap().len(), 4);
+
+        let client = Redirect::new(Fake::default()).max_redirects(0);
+        let req = Request::new(Method::Get, "http://example.com/loop");
+        let res = client.send(req).await?;
+        assert_eq!(res.status(), StatusCode::Found);
+        Ok(())
+    }
+}
@@ -0,0 +1,527 @@
+//! Retrying failed requests, for any `HttpClient`.
+
+use super::{Body, Error, HttpClient, Request, Response};
+use crate::error::ErrorKind;
+
+use futures::future::BoxFuture;
+use http_types::other::RetryAfter;
+use http_types::{Method, StatusCode};
+
+use std::error::Error as StdError;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A client that retries requests on connection errors and transient error responses.
+///
+/// Only requests with idempotent methods are retried, unless other methods are added with
+/// `retry_method`. Their bodies are buffered so they can be sent again, up to `max_body` bytes;
+/// requests with larger or unknown-length bodies are sent once.
+///
+/// Retries wait for the delay of the response's `Retry-After` header if it has one, and back off
+/// exponentially with jitter otherwise. A retry budget shared by all clones of the client limits
+/// retries to a fraction of the requests sent, so that a struggling server isn't overwhelmed.
+///
+/// Delays are waited out with an `async-io` timer, which works on any executor: the first one
+/// starts the thread that drives `async-io`, unless the executor already does.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), http_types::Error> {
+/// use http_client::h1::H1Client;
+/// use http_client::retry::Retry;
+/// use http_client::HttpClient;
+/// use http_types::{Method, Request};
+/// use std::time::Duration;
+///
+/// let client = Retry::new(H1Client::new())
+///     .max_retries(5)
+///     .backoff(Duration::from_millis(50), Duration::from_secs(2));
+/// let res = client.send(Request::new(Method::Get, "http://example.com")).await?;
+/// # Ok(()) }
+/// # #[cfg(not(any(feature = "h1_client_native_tls", feature = "h1_client_rustls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug)]
+pub struct Retry<C> {
+    inner: Arc<C>,
+    config: Arc<Config>,
+    budget: Arc<Budget>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    max_retries: usize,
+    statuses: Vec<StatusCode>,
+    methods: Vec<Method>,
+    base_delay: Duration,
+    max_delay: Duration,
+    max_retry_after: Duration,
+    max_body: usize,
+    /// Copy request extensions of some type from one request to another.
+    extensions: Vec<fn(&Request, &mut Request)>,
+}
+
+/// Copy the extension of type `T` of `from`, if any, to `to`.
+fn copy_extension<T: Clone + Send + Sync + 'static>(from: &Request, to: &mut Request) {
+    if let Some(ext) = from.ext().get::<T>() {
+        to.ext_mut().insert(ext.clone());
+    }
+}
+
+impl<C> Clone for Retry<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            budget: self.budget.clone(),
+        }
+    }
+}
+
+impl<C: HttpClient> Retry<C> {
+    /// Retry the requests sent with `inner` up to 3 times.
+    ///
+    /// By default, `429`, `502`, `503` and `504` responses are retried, backing off from 100
+    /// milliseconds up to 10 seconds, and bodies up to 1 MiB are buffered.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            config: Arc::new(Config {
+                max_retries: 3,
+                statuses: vec![
+                    StatusCode::TooManyRequests,
+                    StatusCode::BadGateway,
+                    StatusCode::ServiceUnavailable,
+                    StatusCode::GatewayTimeout,
+                ],
+                methods: vec![
+                    Method::Get,
+                    Method::Head,
+                    Method::Put,
+                    Method::Delete,
+                    Method::Options,
+                    Method::Trace,
+                ],
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(10),
+                max_retry_after: Duration::from_secs(60),
+                max_body: 1024 * 1024,
+                extensions: vec![
+                    #[cfg(feature = "decompress")]
+                    copy_extension::<crate::decompress::SkipDecompression>,
+                ],
+            }),
+            budget: Arc::new(Budget::new(0.2, 10.0)),
+        }
+    }
+
+    /// Set the number of times a request is retried before giving up.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_retries = max_retries;
+        self
+    }
+
+    /// Set the response statuses that are retried.
+    pub fn retry_on_status(mut self, statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        Arc::make_mut(&mut self.config).statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Also retry requests with `method`, which isn't idempotent by default.
+    ///
+    /// Only do this if the server can cope with receiving the same request more than once.
+    pub fn retry_method(mut self, method: Method) -> Self {
+        Arc::make_mut(&mut self.config).methods.push(method);
+        self
+    }
+
+    /// Wait `base` before the first retry, doubling up to `max` for each further one.
+    ///
+    /// The actual delay is picked at random between zero and that, so that clients don't retry
+    /// in lockstep.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        let config = Arc::make_mut(&mut self.config);
+        config.base_delay = base;
+        config.max_delay = max;
+        self
+    }
+
+    /// Set the longest `Retry-After` delay to wait for.
+    ///
+    /// Responses asking for longer delays are returned rather than retried.
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        Arc::make_mut(&mut self.config).max_retry_after = max;
+        self
+    }
+
+    /// Set the largest request body that's buffered so it can be sent again.
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        Arc::make_mut(&mut self.config).max_body = max_body;
+        self
+    }
+
+    /// Carry request extensions of type `T` over to retries.
+    ///
+    /// The first attempt is the request itself, with all its extensions. Extensions can't be
+    /// copied in general, so retries only get those of the types given here, and those of this
+    /// crate, such as `SkipDecompression`.
+    pub fn keep_extension<T: Clone + Send + Sync + 'static>(mut self) -> Self {
+        Arc::make_mut(&mut self.config)
+            .extensions
+            .push(copy_extension::<T>);
+        self
+    }
+
+    /// Allow `ratio` retries for each request sent, with up to `capacity` retries saved up.
+    ///
+    /// Once the budget is spent, failures are returned rather than retried. It's shared by all
+    /// clones of this client, and starts out full.
+    pub fn retry_budget(mut self, ratio: f64, capacity: f64) -> Self {
+        self.budget = Arc::new(Budget::new(ratio, capacity));
+        self
+    }
+}
+
+impl<C: HttpClient> HttpClient for Retry<C> {
+    fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let client = self.clone();
+        Box::pin(async move { client.retry(req).await })
+    }
+}
+
+impl<C: HttpClient> Retry<C> {
+    async fn retry(&self, mut req: Request) -> Result<Response, Error> {
+        let config = &self.config;
+        self.budget.deposit();
+        if !config.methods.contains(&req.method()) {
+            return self.inner.send(req).await;
+        }
+        let body = match req.len() {
+            Some(len) if len <= config.max_body => req.take_body().into_bytes().await?,
+            _ => {
+                log::trace!("not retrying: the request body can't be sent again");
+                return self.inner.send(req).await;
+            }
+        };
+
+        // Cloning a request drops its extensions, so those that are kept are copied over to
+        // each retry from the template.
+        let mut template = req.clone();
+        for copy in &config.extensions {
+            copy(&req, &mut template);
+        }
+        let mut first = Some(req);
+        let mut retries = 0;
+        loop {
+            let mut attempt = first.take().unwrap_or_else(|| {
+                let mut attempt = template.clone();
+                for copy in &config.extensions {
+                    copy(&template, &mut attempt);
+                }
+                attempt
+            });
+            if !body.is_empty() {
+                attempt.set_body(Body::from_bytes(body.clone()));
+            }
+            let res = self.inner.send(attempt).await;
+            let retry_after = match &res {
+                Ok(res) if config.statuses.contains(&res.status()) => RetryAfter::from_headers(res)
+                    .ok()
+                    .flatten()
+                    .map(|at| at.duration_since(SystemTime::now()).unwrap_or_default()),
+                Err(err) if is_transient(err) => None,
+                _ => return res,
+            };
+            if retries >= config.max_retries || !self.budget.withdraw() {
+                return res;
+            }
+            let delay = match retry_after {
+                Some(delay) if delay > config.max_retry_after => return res,
+                Some(delay) => delay,
+                None => self.delay(retries),
+            };
+            match &res {
+                Ok(res) => log::debug!("retrying {} after {:?}", res.status(), delay),
+                Err(err) => log::debug!("retrying error {} after {:?}", err, delay),
+            }
+            async_io::Timer::after(delay).await;
+            retries += 1;
+        }
+    }
+
+    /// The delay before retry number `retries`, with full jitter.
+    fn delay(&self, retries: usize) -> Duration {
+        let factor = 1u32 << retries.min(31);
+        let delay = self.config.base_delay.saturating_mul(factor);
+        delay.min(self.config.max_delay).mul_f64(fastrand::f64())
+    }
+}
+
+/// Whether `err` is a failure to connect or a dropped connection, which may not happen again.
+fn is_transient(err: &Error) -> bool {
+    if matches!(ErrorKind::of(err), ErrorKind::Connect | ErrorKind::Timeout) {
+        return true;
+    }
+    // Errors from other clients have no kind, but may still wrap an I/O error.
+    let mut source: Option<&(dyn StdError + 'static)> = Some(err.as_ref());
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            use io::ErrorKind::*;
+            return matches!(
+                err.kind(),
+                ConnectionRefused
+                    | ConnectionReset
+                    | ConnectionAborted
+                    | NotConnected
+                    | BrokenPipe
+                    | TimedOut
+                    | UnexpectedEof
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// A token bucket limiting the number of retries.
+#[derive(Debug)]
+struct Budget {
+    tokens: Mutex<f64>,
+    ratio: f64,
+    capacity: f64,
+}
+
+impl Budget {
+    fn new(ratio: f64, capacity: f64) -> Self {
+        Self {
+            tokens: Mutex::new(capacity),
+            ratio,
+            capacity,
+        }
+    }
+
+    fn deposit(&self) {
+        let mut tokens = self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.ratio).min(self.capacity);
+    }
+
+    fn withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap();
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockClient;
+    use http_types::headers::RETRY_AFTER;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn retry(mock: &MockClient) -> Retry<MockClient> {
+        Retry::new(mock.clone()).backoff(Duration::from_millis(1), Duration::from_millis(5))
+    }
+
+    fn unavailable(
+        retry_after: &'static str,
+    ) -> impl Fn(&crate::mock::ReceivedRequest) -> Response {
+        move |_| {
+            let mut res = Response::new(StatusCode::ServiceUnavailable);
+            res.insert_header(RETRY_AFTER, retry_after);
+            res
+        }
+    }
+
+    /// Fail with a reset connection a number of times, then answer with `200 OK`.
+    #[derive(Debug)]
+    struct Flaky(AtomicUsize);
+
+    impl HttpClient for Flaky {
+        fn send(&self, _req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+            let fail = self
+                .0
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            Box::pin(async move {
+                match fail {
+                    Ok(_) => Err(io::Error::from(io::ErrorKind::ConnectionReset).into()),
+                    Err(_) => Ok(Response::new(StatusCode::Ok)),
+                }
+            })
+        }
+    }
+
+    #[async_std::test]
+    async fn retries_statuses_and_replays_bodies() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Put, "/")
+            .body("payload")
+            .times(2)
+            .respond_with(unavailable("0"));
+        mock.expect(Method::Put, "/")
+            .body("payload")
+            .times(1)
+            .respond(StatusCode::Ok, "");
+
+        let mut req = Request::new(Method::Put, "http://api.test/");
+        req.set_body("payload");
+        let res = retry(&mock).send(req).await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn keeps_extensions_across_retries() -> http_types::Result<()> {
+        #[derive(Debug, Clone)]
+        struct Marker;
+
+        /// Answer with `503 Service Unavailable` at first, counting requests with a `Marker`.
+        #[derive(Debug, Default)]
+        struct Marked(AtomicUsize, AtomicUsize);
+
+        impl HttpClient for Marked {
+            fn send(&self, req: Request) -> BoxFuture<'static, Result<Response, Error>> {
+                if req.ext().get::<Marker>().is_some() {
+                    self.1.fetch_add(1, Ordering::SeqCst);
+                }
+                let status = match self.0.fetch_add(1, Ordering::SeqCst) {
+                    0 => StatusCode::ServiceUnavailable,
+                    _ => StatusCode::Ok,
+                };
+                Box::pin(async move { Ok(Response::new(status)) })
+            }
+        }
+
+        let client = Retry::new(Marked::default())
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let mut req = Request::new(Method::Get, "http://api.test/");
+        req.ext_mut().insert(Marker);
+        assert_eq!(client.send(req).await?.status(), StatusCode::Ok);
+        assert_eq!(client.inner.1.load(Ordering::SeqCst), 1);
+
+        let client = Retry::new(Marked::default())
+            .backoff(Duration::from_millis(1), Duration::from_millis(5))
+            .keep_extension::<Marker>();
+        let mut req = Request::new(Method::Get, "http://api.test/");
+        req.ext_mut().insert(Marker);
+        assert_eq!(client.send(req).await?.status(), StatusCode::Ok);
+        assert_eq!(client.inner.1.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[cfg(feature = "decompress")]
+    #[async_std::test]
+    async fn keeps_skip_decompression_across_retries() -> http_types::Result<()> {
+        use crate::decompress::{Decompress, SkipDecompression};
+
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/")
+            .times(1)
+            .respond_with(unavailable("0"));
+        mock.expect(Method::Get, "/").respond(StatusCode::Ok, "");
+        let client = Retry::new(Decompress::new(mock.clone()))
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+
+        let mut req = Request::new(Method::Get, "http://api.test/");
+        req.ext_mut().insert(SkipDecompression);
+        let res = client.send(req).await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        let received = mock.received();
+        assert_eq!(received.len(), 2);
+        assert!(received
+            .iter()
+            .all(|req| req.header("accept-encoding").is_empty()));
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn retries_connection_errors() -> http_types::Result<()> {
+        let client = Retry::new(Flaky(AtomicUsize::new(2)))
+            .backoff(Duration::from_millis(1), Duration::from_millis(5));
+        let res = client
+            .send(Request::new(Method::Get, "http://api.test/"))
+            .await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+
+        let client = client.max_retries(1);
+        client.inner.0.store(2, Ordering::SeqCst);
+        let req = Request::new(Method::Get, "http://api.test/");
+        assert!(client.send(req).await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn only_retries_what_it_may() -> http_types::Result<()> {
+        // Not idempotent.
+        let mock = MockClient::new();
+        mock.expect(Method::Post, "/")
+            .times(1)
+            .respond(StatusCode::ServiceUnavailable, "");
+        let res = retry(&mock)
+            .send(Request::new(Method::Post, "http://api.test/"))
+            .await?;
+        assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+        mock.verify().unwrap();
+
+        // Opted in.
+        let mock = MockClient::new();
+        mock.expect(Method::Post, "/")
+            .times(1)
+            .respond(StatusCode::ServiceUnavailable, "");
+        mock.expect(Method::Post, "/").respond(StatusCode::Ok, "");
+        let res = retry(&mock)
+            .retry_method(Method::Post)
+            .send(Request::new(Method::Post, "http://api.test/"))
+            .await?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        mock.verify().unwrap();
+
+        // Too large to buffer.
+        let mock = MockClient::new();
+        mock.expect(Method::Put, "/")
+            .times(1)
+            .respond(StatusCode::ServiceUnavailable, "");
+        let mut req = Request::new(Method::Put, "http://api.test/");
+        req.set_body("payload");
+        let res = retry(&mock).max_body(4).send(req).await?;
+        assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+        mock.verify().unwrap();
+
+        // Asked to come back too late.
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/")
+            .times(1)
+            .respond_with(unavailable("120"));
+        let res = retry(&mock)
+            .send(Request::new(Method::Get, "http://api.test/"))
+            .await?;
+        assert_eq!(res.status(), StatusCode::ServiceUnavailable);
+        mock.verify().unwrap();
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn stops_when_the_budget_is_spent() -> http_types::Result<()> {
+        let mock = MockClient::new();
+        mock.expect(Method::Get, "/")
+            .times(3)
+            .respond(StatusCode::BadGateway, "");
+        let client = retry(&mock).max_retries(5).retry_budget(0.0, 2.0);
+        let res = client
+            .send(Request::new(Method::Get, "http://api.test/"))
+            .await?;
+        assert_eq!(res.status(), StatusCode::BadGateway);
+        mock.verify().unwrap();
+        Ok(())
+    }
+}
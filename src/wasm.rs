@@ -40,7 +40,7 @@ impl HttpClient for WasmClient {
             response.set_body(Body::from(body));
             for (name, value) in res.headers() {
                 let name: http_types::headers::HeaderName = name.parse().unwrap();
-                response.insert_header(&name, value);
+                response.append_header(&name, value);
             }
 
             Ok(response)
@@ -78,8 +78,7 @@ mod fetch {
     use std::iter::{IntoIterator, Iterator};
     use std::pin::Pin;
 
-    use http_types::StatusCode;
-
+    use crate::error::{kind_error, ErrorKind};
     use crate::Error;
 
     /// Create a new fetch request.
@@ -108,9 +107,10 @@ mod fetch {
             // needs to be pinned and retained inside the Request because the Uint8Array passed to
             // js is just a portal into WASM linear memory, and if the underlying data is moved the
             // js ref will become silently invalid
-            let body_buf = body.into_bytes().await.map_err(|_| {
-                Error::from_str(StatusCode::BadRequest, "could not read body into a buffer")
-            })?;
+            let body_buf = body
+                .into_bytes()
+                .await
+                .map_err(|_| kind_error(ErrorKind::Body, "could not read body into a buffer"))?;
             let body_pinned = Pin::new(body_buf);
             if body_pinned.len() > 0 {
                 let uint_8_array = unsafe { js_sys::Uint8Array::view(&body_pinned) };
@@ -118,24 +118,25 @@ mod fetch {
             }
 
             let request = web_sys::Request::new_with_str_and_init(&uri, &init).map_err(|e| {
-                Error::from_str(
-                    StatusCode::BadRequest,
+                kind_error(
+                    ErrorKind::InvalidRequest,
                     format!("failed to create request: {:?}", e),
                 )
             })?;
 
             // add any fetch headers
             let headers: &mut super::Headers = req.as_mut();
-            for (name, value) in headers.iter() {
+            for (name, values) in headers.iter() {
                 let name = name.as_str();
-                let value = value.as_str();
-
-                request.headers().set(name, value).map_err(|_| {
-                    Error::from_str(
-                        StatusCode::BadRequest,
-                        format!("could not add header: {} = {}", name, value),
-                    )
-                })?;
+                for value in values.iter() {
+                    let value = value.as_str();
+                    request.headers().append(name, value).map_err(|_| {
+                        kind_error(
+                            ErrorKind::InvalidRequest,
+                            format!("could not add header: {} = {}", name, value),
+                        )
+                    })?;
+                }
             }
 
             Ok(Self {
@@ -150,9 +151,16 @@ mod fetch {
             // Send the request.
             let window = window().expect("A global window object could not be found");
             let promise = window.fetch_with_request(&self.request);
-            let resp = JsFuture::from(promise)
-                .await
-                .map_err(|e| Error::from_str(StatusCode::BadRequest, format!("{:?}", e)))?;
+            let resp = JsFuture::from(promise).await.map_err(|e| {
+                // Fetch rejects with an `AbortError` when aborted, and a `TypeError` for any
+                // network failure, without telling which.
+                let name = Reflect::get(&e, &"name".into()).ok();
+                let kind = match name.and_then(|name| name.as_string()).as_deref() {
+                    Some("AbortError") => ErrorKind::Cancelled,
+                    _ => ErrorKind::Connect,
+                };
+                kind_error(kind, format!("{:?}", e))
+            })?;
 
             debug_assert!(resp.is_instance_of::<web_sys::Response>());
             let res: web_sys::Response = resp.dyn_into().unwrap();
@@ -0,0 +1,289 @@
+//! Behavior every `HttpClient` backend must share, checked against a local tide server.
+//!
+//! Each check is a generic async function over `impl HttpClient`, and `conformance!` turns the
+//! whole list into tests for one backend.
+
+// The checks go unused when no backend is enabled.
+#![allow(dead_code, unused_macros)]
+
+use async_std::task;
+#[cfg(feature = "decompress")]
+use http_client::decompress::Decompress;
+use http_client::error::ErrorKind;
+use http_client::redirect::{Redirect, RedirectChain};
+use http_client::HttpClient;
+use http_types::headers::{ACCEPT_ENCODING, CONTENT_ENCODING, LOCATION};
+use http_types::{Body, Method, Request, StatusCode, Url, Version};
+
+use std::time::Duration;
+
+const LARGE: usize = 4 * 1024 * 1024;
+
+const LINES_GZ: &[u8] = include_bytes!("fixtures/lines.gz");
+
+/// Start a test server, returning its base URL.
+///
+/// * `/echo` answers any method with the request body, the method in `x-method`, each value of
+///   the `x-multi` request headers in an `x-echo` header, and two cookies.
+/// * `/status/:code` answers with an empty response of that status.
+/// * `/large` answers with `LARGE` bytes of known length, `/chunked` without a length.
+/// * `/redirect` redirects to `/echo` with a `302`.
+/// * `/gzip` answers with a gzip-encoded body if the request accepts it.
+async fn serve() -> Url {
+    let mut app = tide::new();
+    app.at("/echo")
+        .all(|mut req: tide::Request<()>| async move {
+            let mut res = tide::Response::new(StatusCode::Ok);
+            res.insert_header("x-method", req.method().to_string());
+            if let Some(values) = req.header("x-multi") {
+                for value in values.iter() {
+                    res.append_header("x-echo", value.as_str());
+                }
+            }
+            res.append_header("set-cookie", "a=1");
+            res.append_header("set-cookie", "b=2");
+            res.set_body(req.body_bytes().await?);
+            Ok(res)
+        });
+    app.at("/status/:code")
+        .all(|req: tide::Request<()>| async move {
+            let code: u16 = req.param("code")?.parse()?;
+            Ok(tide::Response::new(code))
+        });
+    app.at("/large").get(|_| async { Ok(Body::from(large())) });
+    app.at("/chunked").get(|_| async {
+        let reader = futures::io::Cursor::new(large());
+        Ok(Body::from_reader(reader, None))
+    });
+    app.at("/redirect").all(|_| async {
+        let mut res = tide::Response::new(StatusCode::Found);
+        res.insert_header(LOCATION, "/echo");
+        Ok(res)
+    });
+    app.at("/gzip").get(|req: tide::Request<()>| async move {
+        let mut res = tide::Response::new(StatusCode::Ok);
+        let accepted = req.header(ACCEPT_ENCODING).map(|h| h.as_str().to_owned());
+        if accepted.unwrap_or_default().contains("gzip") {
+            res.insert_header(CONTENT_ENCODING, "gzip");
+            res.set_body(LINES_GZ);
+        } else {
+            res.set_body("identity");
+        }
+        Ok(res)
+    });
+
+    let port = portpicker::pick_unused_port().unwrap();
+    task::spawn(app.listen(("127.0.0.1", port)));
+    while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+        task::sleep(Duration::from_millis(10)).await;
+    }
+    Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap()
+}
+
+fn large() -> Vec<u8> {
+    (0..LARGE).map(|i| (i % 251) as u8).collect()
+}
+
+async fn methods(client: impl HttpClient) {
+    let url = serve().await.join("echo").unwrap();
+    let methods = [
+        Method::Get,
+        Method::Post,
+        Method::Put,
+        Method::Delete,
+        Method::Patch,
+        Method::Options,
+    ];
+    for method in methods.iter() {
+        let res = client
+            .send(Request::new(*method, url.clone()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(res["x-method"], method.to_string().as_str());
+    }
+
+    let mut res = client.send(Request::new(Method::Head, url)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::Ok);
+    assert_eq!(res.body_string().await.unwrap(), "");
+}
+
+async fn status_codes(client: impl HttpClient) {
+    let url = serve().await;
+    for code in [200, 201, 204, 400, 404, 418, 500, 503].iter() {
+        let url = url.join(&format!("status/{}", code)).unwrap();
+        let mut res = client.send(Request::new(Method::Get, url)).await.unwrap();
+        assert_eq!(res.status() as u16, *code);
+        assert_eq!(res.body_string().await.unwrap(), "");
+    }
+}
+
+async fn repeated_headers(client: impl HttpClient) {
+    let url = serve().await.join("echo").unwrap();
+    let mut req = Request::new(Method::Get, url);
+    req.append_header("x-multi", "one");
+    req.append_header("x-multi", "two");
+    req.append_header("x-multi", "three");
+
+    let res = client.send(req).await.unwrap();
+    let values = |name| -> Vec<String> {
+        res.header(name)
+            .map(|values| values.iter().map(|v| v.as_str().to_owned()).collect())
+            .unwrap_or_default()
+    };
+    assert_eq!(values("x-echo"), vec!["one", "two", "three"]);
+    assert_eq!(values("set-cookie"), vec!["a=1", "b=2"]);
+}
+
+async fn empty_bodies(client: impl HttpClient) {
+    let url = serve().await.join("echo").unwrap();
+    let mut res = client.send(Request::new(Method::Post, url)).await.unwrap();
+    assert_eq!(res.body_string().await.unwrap(), "");
+}
+
+async fn large_bodies(client: impl HttpClient) {
+    let url = serve().await;
+
+    let mut req = Request::new(Method::Post, url.join("echo").unwrap());
+    req.set_body(large());
+    let mut res = client.send(req).await.unwrap();
+    assert!(res.body_bytes().await.unwrap() == large());
+
+    let req = Request::new(Method::Get, url.join("large").unwrap());
+    let mut res = client.send(req).await.unwrap();
+    assert_eq!(res.len(), Some(LARGE));
+    assert!(res.body_bytes().await.unwrap() == large());
+}
+
+async fn chunked_bodies(client: impl HttpClient) {
+    let url = serve().await;
+
+    let mut req = Request::new(Method::Post, url.join("echo").unwrap());
+    req.set_body(Body::from_reader(futures::io::Cursor::new(large()), None));
+    let mut res = client.send(req).await.unwrap();
+    assert!(res.body_bytes().await.unwrap() == large());
+
+    let req = Request::new(Method::Get, url.join("chunked").unwrap());
+    let mut res = client.send(req).await.unwrap();
+    assert_eq!(res.len(), None);
+    assert!(res.body_bytes().await.unwrap() == large());
+}
+
+async fn versions(client: impl HttpClient) {
+    let url = serve().await.join("echo").unwrap();
+    let res = client.send(Request::new(Method::Get, url)).await.unwrap();
+    assert_eq!(res.version(), Some(Version::Http1_1));
+}
+
+async fn socket_addrs(client: impl HttpClient) {
+    let url = serve().await.join("echo").unwrap();
+    let server = format!("127.0.0.1:{}", url.port().unwrap());
+    let res = client.send(Request::new(Method::Get, url)).await.unwrap();
+    assert_eq!(res.peer_addr(), Some(server.as_str()));
+    // Not every backend can tell which local address it used.
+    if let Some(local_addr) = res.local_addr() {
+        assert!(local_addr.starts_with("127.0.0.1:"), "{}", local_addr);
+    }
+}
+
+async fn redirects(client: impl HttpClient) {
+    let url = serve().await.join("redirect").unwrap();
+
+    // Backends leave redirects alone by themselves.
+    let res = client
+        .send(Request::new(Method::Get, url.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::Found);
+    assert_eq!(res[LOCATION], "/echo");
+
+    let client = Redirect::new(client);
+    let res = client
+        .send(Request::new(Method::Get, url.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::Ok);
+    let chain: &RedirectChain = res.ext().get().unwrap();
+    assert_eq!(chain.urls(), &[url.clone(), url.join("echo").unwrap()]);
+}
+
+async fn compression(client: impl HttpClient) {
+    let url = serve().await.join("gzip").unwrap();
+
+    // Backends neither ask for compressed responses nor decode them by themselves.
+    let mut res = client
+        .send(Request::new(Method::Get, url.clone()))
+        .await
+        .unwrap();
+    assert_eq!(res.body_string().await.unwrap(), "identity");
+    let mut req = Request::new(Method::Get, url.clone());
+    req.insert_header(ACCEPT_ENCODING, "gzip");
+    let mut res = client.send(req).await.unwrap();
+    assert_eq!(res[CONTENT_ENCODING], "gzip");
+    assert!(res.body_bytes().await.unwrap() == LINES_GZ);
+
+    #[cfg(feature = "decompress")]
+    {
+        let client = Decompress::new(client);
+        let mut res = client.send(Request::new(Method::Get, url)).await.unwrap();
+        assert!(res.header(CONTENT_ENCODING).is_none());
+        let body = res.body_string().await.unwrap();
+        assert_eq!(body.lines().count(), 1000);
+    }
+}
+
+async fn errors(client: impl HttpClient) {
+    let port = portpicker::pick_unused_port().unwrap();
+    let url = format!("http://127.0.0.1:{}/", port);
+    let req = Request::new(Method::Get, url.as_str());
+    let err = client.send(req).await.expect_err("connection refused");
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Connect, "{}", err);
+
+    let req = Request::new(Method::Get, "gopher://127.0.0.1/");
+    assert!(client.send(req).await.is_err(), "unsupported scheme");
+
+    let req = Request::new(Method::Get, "data:text/plain,hello");
+    let err = client.send(req).await.expect_err("missing host");
+    assert_eq!(ErrorKind::of(&err), ErrorKind::InvalidRequest, "{}", err);
+}
+
+macro_rules! conformance {
+    ($backend:ident, $test:meta, $client:expr) => {
+        conformance!(@checks $backend, $test, $client, [
+            methods,
+            status_codes,
+            repeated_headers,
+            empty_bodies,
+            large_bodies,
+            chunked_bodies,
+            versions,
+            socket_addrs,
+            redirects,
+            compression,
+            errors,
+        ]);
+    };
+    (@checks $backend:ident, $test:meta, $client:expr, [$($check:ident,)*]) => {
+        mod $backend {
+            $(
+                #[$test]
+                async fn $check() {
+                    super::$check($client).await;
+                }
+            )*
+        }
+    };
+}
+
+#[cfg(any(feature = "h1_client_native_tls", feature = "h1_client_rustls"))]
+conformance!(h1, async_std::test, http_client::h1::H1Client::new());
+
+#[cfg(feature = "curl_client")]
+conformance!(
+    isahc,
+    async_std::test,
+    http_client::isahc::IsahcClient::new()
+);
+
+#[cfg(feature = "hyper_client")]
+conformance!(hyper, tokio::test, http_client::hyper::HyperClient::new());